@@ -10,68 +10,285 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use anyhow::{anyhow, Context};
+use crate::config::{
+    bus_id, znet_iface, BondConfig, DiskConfig, IpConfig, MultipathDisks, NetworkConfig, ServeAddr,
+    VmurDevices, ZfcpPath,
+};
+use crate::error::Error;
+use crate::images::{ca_certificates, compressed_name, image_dir};
+use anyhow::{anyhow, bail, Context};
 use chrono::prelude::*;
-use clap::{AppSettings, Parser, ValueEnum};
+use clap::{AppSettings, IntoApp, Parser, ValueEnum};
+use clap_complete::Shell;
+use log::warn;
 use reqwest::Url;
 use std::borrow::Cow;
+use std::convert::{Infallible, TryFrom};
 use std::env::current_dir;
 use std::fmt;
+use std::io::Write;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use url::ParseError;
+
+/// Nameserver of a static --ip without --dns
+const DEFAULT_DNS: IpAddr = IpAddr::V4(Ipv4Addr::new(172, 23, 0, 1));
 
 #[derive(Debug, Parser)]
 #[clap(name = "zvmhelper", version)]
+#[clap(long_version = concat!(
+    env!("CARGO_PKG_VERSION"),
+    "\ncommit: ",
+    env!("ZVMHELPER_GIT_COMMIT"),
+    "\nbuilt: ",
+    env!("ZVMHELPER_BUILD_DATE"),
+    "\nvariants: fcos, rhcos",
+))]
+#[clap(
+    after_help = "Site defaults can be set through the environment variables shown \
+for each option or a --config file, command line flags take precedence over them \
+and the file over the environment and the built-in defaults."
+)]
 #[clap(global_setting(AppSettings::DeriveDisplayOrder))]
 #[clap(args_conflicts_with_subcommands = true)]
 #[clap(disable_help_subcommand = true)]
 #[clap(help_expected = true)]
+// --config values come first and are overridden
+#[clap(args_override_self = true)]
 pub enum Cmd {
     /// Install zVM using given arguments
     Install(InstallConfig),
+    /// Run the install checks without touching the guest or the devices
+    Verify(InstallConfig),
+    /// Print the parm line of the install, nothing is downloaded or
+    /// punched
+    Parm(InstallConfig),
+    /// Print the completion script of a shell
+    Completions {
+        /// Shell to complete in
+        #[clap(value_enum)]
+        shell: Shell,
+    },
 }
 
-#[derive(Debug, Parser)]
+impl Cmd {
+    /// Options of the subcommand, if it installs
+    pub fn options(&self) -> Option<&InstallConfig> {
+        match self {
+            Self::Install(cfg) | Self::Verify(cfg) | Self::Parm(cfg) => Some(cfg),
+            Self::Completions { .. } => None,
+        }
+    }
+
+    /// Writes the completion script of `shell`, covering all subcommands
+    pub fn completions(shell: Shell, out: &mut dyn Write) {
+        clap_complete::generate(shell, &mut Self::command(), "zvmhelper", out);
+    }
+
+    #[cfg(test)]
+    pub fn config(self) -> InstallConfig {
+        match self {
+            Self::Install(cfg) | Self::Verify(cfg) | Self::Parm(cfg) => cfg,
+            Self::Completions { .. } => panic!("completions have no install config"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
 pub struct InstallConfig {
+    /// TOML or YAML file of option values, keyed by their long names.
+    /// Command line flags take precedence over it.
+    #[clap(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
     /// zVM target
-    #[clap(long, short, value_name = "zVM", default_value = "a3e29008")]
+    #[clap(
+        long,
+        short,
+        value_name = "zVM",
+        env = "ZVM_GUEST",
+        default_value = "a3e29008"
+    )]
     pub zvm: String,
 
-    /// zVM target
-    #[clap(long, short, value_name = "IGNITION_CONFIG")]
+    /// Ignition config URL, or a local file served to the guest on an
+    /// ephemeral port unless --ignition-serve is given
+    #[clap(
+        long,
+        short,
+        value_name = "IGNITION_CONFIG",
+        env = "ZVM_IGNITION",
+        required_unless_present = "ignition-serve",
+        default_value = ""
+    )]
     pub ignition: String,
 
-    /// dfltcc option
+    /// Ignition config fragment, given once per fragment. They are merged
+    /// in order into ignition.<zVM>.ign in the image directory, which must
+    /// then be served at the --ignition URL or with --ignition-serve
+    #[clap(long, value_name = "PATH")]
+    pub ignition_file: Vec<PathBuf>,
+
+    /// Serve the merged --ignition-file or --ssh-key config on
+    /// `<addr>:<port>`, or on `:<port>` of the address the guest's --ip is
+    /// routed through, until the guest fetched it. The served URL replaces
+    /// --ignition.
+    #[clap(long, value_name = "ADDR:PORT", conflicts_with = "fleet")]
+    pub ignition_serve: Option<ServeAddr>,
+
+    /// --ignition as given, once --ignition-serve replaced it
+    #[clap(skip)]
+    pub ignition_source: Option<String>,
+
+    /// SSH public key of the `core` user, or a file of them, given once
+    /// per key or file. The keys are added to the merged --ignition-file
+    /// config, or to a served config merging --ignition.
+    #[clap(long, value_name = "PATH|KEY", conflicts_with = "fleet")]
+    pub ssh_key: Vec<String>,
+
+    /// Encrypt the root filesystem of the installed system with LUKS,
+    /// bound to --tang-url servers. The LUKS config is added like the
+    /// --ssh-key keys are.
+    #[clap(long, conflicts_with = "fleet")]
+    pub luks: bool,
+
+    /// Tang server unlocking the --luks root, given once per server
+    #[clap(long, value_name = "URL", requires = "luks")]
+    pub tang_url: Vec<Url>,
+
+    /// Thumbprint of the signing key of each --tang-url, in order
+    #[clap(long, value_name = "THUMBPRINT", requires = "tang-url")]
+    pub tang_thumbprint: Vec<String>,
+
+    /// Also bind the --luks root to a TPM2 device
+    #[clap(long, requires = "luks")]
+    pub tpm2: bool,
+
+    /// Seconds to wait for the guest to fetch the served Ignition config
+    #[clap(long, value_name = "SECS", default_value = "1800")]
+    pub ignition_serve_timeout: u64,
+
+    /// Boot the installer in FIPS mode (fips=1), see the README for disk
+    /// encryption
+    #[clap(long)]
+    pub fips: bool,
+
+    /// coreos.inst.insecure=yes, letting the installer write an unsigned
+    /// image. `no` requires verified fetches, --ignition and the rootfs
+    /// should then be https URLs
+    #[clap(
+        long,
+        value_name = "BOOL",
+        default_value = "yes",
+        action = clap::ArgAction::Set,
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    pub insecure: bool,
+
+    /// Kernel console `<device>[,<options>]` of the installer, given once
+    /// per console in order, e.g. --console=ttyS0,115200. A bare --console
+    /// is the s390x SCLP console, ttysclp0
+    #[clap(
+        long,
+        value_name = "CONSOLE",
+        require_equals = true,
+        min_values = 0,
+        default_missing_value = "ttysclp0",
+        multiple_occurrences = true,
+        value_parser = parse_console
+    )]
+    pub console: Vec<String>,
+
+    /// s390 zlib hardware acceleration (dfltcc=)
+    #[clap(value_enum)]
     #[clap(long, value_name = "DFLTCC")]
-    pub dfltcc: Option<bool>,
+    pub dfltcc: Option<Dfltcc>,
+
+    /// coreos.live.rootfs_url, overrides the rootfs of the images, which
+    /// is then not needed locally
+    #[clap(long, value_name = "URL", value_parser = parse_rootfs_url)]
+    pub rootfs_url: Option<Url>,
+
+    /// Also download a remote rootfs of the images into the image
+    /// directory, the guest still fetches it from its URL. A local one is
+    /// always checked
+    #[clap(long, conflicts_with = "rootfs-url")]
+    pub download_rootfs: bool,
+
+    /// Decompress a gzip or zstd compressed rootfs once downloaded and its
+    /// SHA256 checked, into its file name without the .gz/.zst extension.
+    /// The guest can't boot from a compressed coreos.live.rootfs_url
+    #[clap(long)]
+    pub decompress: bool,
+
+    /// Platform ID of the installed system (coreos.inst.platform_id), the
+    /// installer implies 'metal' for z/VM and LPAR, 'qemu' is KVM
+    #[clap(
+        long,
+        value_name = "ID",
+        value_parser = ["metal", "qemu", "ibmcloud", "openstack", "kubevirt"]
+    )]
+    pub platform_id: Option<String>,
 
     /// extra kargs
     #[clap(long, short, value_name = "CMDLINE")]
     pub cmdline: Option<String>,
 
-    /// Dasd
-    #[clap(long, value_name = "DASD")]
+    /// Extra karg `KEY=VALUE` or `KEY`, given once per karg, a repeated
+    /// key overrides the earlier value
+    #[clap(long, value_name = "KARG", value_parser = parse_karg)]
+    pub karg: Vec<String>,
+
+    /// Kind of disk to install to, given by --disk
+    #[clap(value_enum)]
+    #[clap(
+        long,
+        value_name = "KIND",
+        requires = "disk",
+        conflicts_with_all = &["dasd", "edev", "scsi", "mp"]
+    )]
+    pub target: Option<TargetKind>,
+
+    /// Install disk of --target: a DASD device number, or a zFCP path
+    /// `<devno>,<wwpn>,<lun>` given once per path for multipath
+    #[clap(
+        long,
+        value_name = "DISK",
+        requires = "target",
+        value_parser = parse_disk
+    )]
+    pub disk: Vec<String>,
+
+    /// Dasd, same as --target dasd --disk DASD
+    #[clap(long, value_name = "DASD", value_parser = parse_bus_id)]
     pub dasd: Option<String>,
 
-    /// Edev
+    /// Edev, same as --target edev --disk EDEV
     #[clap(
         long,
         value_name = "EDEV",
         conflicts_with = "dasd",
         conflicts_with = "scsi",
-        conflicts_with = "mp"
+        conflicts_with = "mp",
+        value_parser = parse_bus_id
     )]
     pub edev: Option<String>,
 
-    /// Scsi
+    /// Scsi, same as --target scsi --disk SCSI
     #[clap(
         long,
         value_name = "SCSI",
         conflicts_with = "dasd",
         conflicts_with = "edev",
-        conflicts_with = "mp"
+        conflicts_with = "mp",
+        value_parser = parse_scsi
     )]
     pub scsi: Option<String>,
 
-    /// Multipath
+    /// Multipath zFCP path, `<devno>,<wwpn>,<lun>[,<lun>...]`, given once
+    /// per path, all paths must lead to the same LUNs
     #[clap(
         long,
         value_name = "MULTIPATH",
@@ -79,53 +296,480 @@ pub struct InstallConfig {
         conflicts_with = "scsi",
         conflicts_with = "edev"
     )]
-    pub mp: Option<Vec<String>>,
+    pub mp: Option<Vec<ZfcpPath>>,
 
-    /// zVM network device (rd.znet)
+    /// Device-mapper name of the multipath install disk, when it isn't
+    /// `mpatha` (an alias from multipath.conf or other maps found first)
+    #[clap(long, value_name = "NAME")]
+    pub mp_name: Option<String>,
+
+    /// zVM network device (rd.znet), given once per interface of a
+    /// multi-NIC guest
     #[clap(
         long,
         value_name = "ZNET",
+        env = "ZVM_ZNET",
         default_value = "qeth,0.0.bdf0,0.0.bdf1,0.0.bdf2,layer2=1,portno=0"
     )]
-    pub znet: String,
+    pub znet: Vec<String>,
 
-    /// Guest ip= karg
+    /// Guest ip= karg: `<autoconf>`, `<nic>:<autoconf>[:<mtu>[:<mac>]]` or
+    /// the static form, which gets its gateway and netmask (dotted or
+    /// prefix length) checked against the client address. Given once per
+    /// --znet, in the same order
     #[clap(
         long,
         value_name = "IP",
-        default_value = "172.23.237.227::172.23.0.1:255.255.0.0:coreos:encbdf0:none"
+        env = "ZVM_IP",
+        default_value = "172.23.237.227::172.23.0.1:255.255.0.0:coreos:encbdf0:none",
+        value_parser = parse_ip
     )]
-    pub ip: String,
+    pub ip: Vec<IpConfig>,
+
+    /// Configure every --znet interface through DHCP instead of --ip
+    #[clap(long, conflicts_with = "ip")]
+    pub dhcp: bool,
+
+    /// MTU of the interfaces, one for all of them or one per --ip in
+    /// order, e.g. 9000 on jumbo-frame networks
+    #[clap(long, value_name = "MTU", value_parser = clap::value_parser!(u16).range(576..=9000))]
+    pub mtu: Vec<u16>,
+
+    /// Bond `<name>:<slave>,<slave>[:<options>[:<mtu>]]` of --znet
+    /// interfaces, e.g. `bond0:encbdf0,enc600:mode=active-backup`. The
+    /// first --ip then configures the bond, the next ones the --znet
+    /// interfaces outside of it
+    #[clap(long, value_name = "BOND")]
+    pub bond: Option<BondConfig>,
+
+    /// 802.1Q VLAN id of the first interface, the ip= karg then
+    /// configures the `<nic>.<id>` VLAN interface on top of it
+    #[clap(long, value_name = "ID", value_parser = clap::value_parser!(u16).range(1..=4094))]
+    pub vlan: Option<u16>,
+
+    /// Accept an IPv4 gateway or peer for an IPv6 client address in --ip,
+    /// or the reverse
+    #[clap(long)]
+    pub allow_mixed_ip_families: bool,
+
+    /// Guest hostname, replaces the one in the first static --ip
+    #[clap(long, value_name = "HOSTNAME")]
+    pub hostname: Option<String>,
+
+    /// Guest network interface, replaces the one in a static --ip, which
+    /// otherwise follows the first --znet device
+    #[clap(long, alias = "iface", value_name = "NIC")]
+    pub nic: Option<String>,
+
+    /// rd.neednet=1, `auto` sets it when the Ignition config or the rootfs
+    /// is fetched over the network
+    #[clap(value_enum)]
+    #[clap(long, value_name = "WHEN", default_value = "auto")]
+    pub neednet: NeedNet,
+
+    /// Guest nameserver= karg, IPv4 or IPv6. ZVM_DNS takes a comma
+    /// separated list. Defaults to 172.23.0.1 with a static --ip, DHCP
+    /// hands out its own; an empty --dns '' sets none
+    #[clap(
+        long,
+        value_name = "NAMESERVER",
+        env = "ZVM_DNS",
+        use_value_delimiter = true,
+        value_parser = parse_nameserver
+    )]
+    pub dns: Vec<Option<IpAddr>>,
+
+    /// Bearer token for artifact requests
+    #[clap(
+        long,
+        value_name = "TOKEN",
+        env = "ZVM_HTTP_AUTH_BEARER",
+        hide_env_values = true,
+        conflicts_with = "http-auth-basic"
+    )]
+    pub http_auth_bearer: Option<Secret>,
+
+    /// Basic credentials for artifact requests
+    #[clap(
+        long,
+        value_name = "USER:PASS",
+        env = "ZVM_HTTP_AUTH_BASIC",
+        hide_env_values = true,
+        value_parser = parse_basic_auth
+    )]
+    pub http_auth_basic: Option<Secret>,
+
+    /// Host the --http-auth-* credentials are sent to, any port when none
+    /// is given. The host of the builder --url or of the live kernel by
+    /// default, no other host gets them
+    #[clap(long, value_name = "HOST[:PORT]", value_parser = parse_auth_host)]
+    pub http_auth_host: Option<AuthHost>,
+
+    /// Proxy for http:// downloads, HTTP_PROXY by default
+    #[clap(long, value_name = "URL", env = "HTTP_PROXY", hide_env_values = true)]
+    pub http_proxy: Option<Url>,
+
+    /// Proxy for https:// downloads, HTTPS_PROXY by default
+    #[clap(long, value_name = "URL", env = "HTTPS_PROXY", hide_env_values = true)]
+    pub https_proxy: Option<Url>,
+
+    /// Hosts, domains and networks reached without the proxy, NO_PROXY
+    /// takes a comma separated list
+    #[clap(
+        long,
+        value_name = "HOST",
+        env = "NO_PROXY",
+        use_value_delimiter = true
+    )]
+    pub no_proxy: Vec<String>,
+
+    /// PEM file of extra root certificates trusted for HTTPS downloads and
+    /// Ignition fetches, e.g. of a private CA
+    #[clap(long, value_name = "PATH")]
+    pub ca_cert: Option<PathBuf>,
+
+    /// Don't verify HTTPS certificates at all. Unsafe: anyone on the path
+    /// can then substitute the images or the Ignition config
+    #[clap(long, conflicts_with = "ca-cert")]
+    pub insecure_tls: bool,
+
+    /// Maximum number of HTTP redirects to follow, the chain is reported
+    /// when it is exceeded
+    #[clap(long, value_name = "N", default_value = "10")]
+    pub max_redirects: usize,
+
+    /// Retries of a download failing with a transient error (connection
+    /// failure, timeout, 5xx), with an exponential backoff from 1s
+    #[clap(long, value_name = "N", default_value = "3")]
+    pub retries: u32,
+
+    /// Images downloaded at the same time. With 1 a progress bar is drawn,
+    /// parallel downloads print a progress line each every few seconds
+    #[clap(long, value_name = "N", default_value = "3", value_parser = clap::value_parser!(u16).range(1..))]
+    pub max_parallel: u16,
+
+    /// Seconds to wait for an HTTP connection, and for data while a
+    /// transfer stalls. Slow but progressing transfers are never cut
+    #[clap(long, value_name = "SECS", default_value = "30")]
+    pub connect_timeout: u64,
+
+    /// Seconds a single image download may take at most, unlimited by
+    /// default. A download exceeding it fails without being retried
+    #[clap(long, value_name = "SECS")]
+    pub timeout: Option<u64>,
+
+    /// Spool class of the punched reader files
+    #[clap(long, value_name = "CLASS", value_parser = parse_spool_class)]
+    pub spool_class: Option<char>,
+
+    /// Reader file name the kernel is punched as, `<name>.<type>`
+    #[clap(long, value_name = "NAME", default_value = "coreos.kernel", value_parser = parse_spool_name)]
+    pub kernel_name: String,
+
+    /// Reader file name the parm is punched as
+    #[clap(long, value_name = "NAME", default_value = "coreos.parm", value_parser = parse_spool_name)]
+    pub parm_name: String,
+
+    /// Reader file name the initrd is punched as
+    #[clap(long, value_name = "NAME", default_value = "coreos.initrd", value_parser = parse_spool_name)]
+    pub initrd_name: String,
+
+    /// Purges of the guest's reader repeated while it still has files,
+    /// 2s apart
+    #[clap(long, value_name = "N", default_value = "3")]
+    pub purge_retries: u32,
+
+    /// Offline the reader/punch devices brought online for the install
+    /// once done, they are always offlined when the install fails
+    #[clap(long)]
+    pub restore_devices: bool,
+
+    /// Device numbers of the helper's virtual reader, punch and printer,
+    /// brought online to punch the files
+    #[clap(long, value_name = "RDR,PUN,PRT", default_value = "c,d,e")]
+    pub vmur_devices: VmurDevices,
+
+    /// Run the vmcp, vmur and device commands over SSH on this z/VM
+    /// guest, the punched files are copied there first with scp. Needs
+    /// key-based authentication.
+    #[clap(long, value_name = "USER@HOST", value_parser = parse_ssh_host)]
+    pub ssh: Option<String>,
+
+    /// Don't check that the guest is logged on and its reader can be
+    /// queried before purging it
+    #[clap(long)]
+    pub no_check_logon: bool,
+
+    /// Check that the kernel image exists before downloading anything
+    #[clap(long)]
+    pub preflight: bool,
+
+    /// Ping the gateway of --ip before installing and warn about Ignition
+    /// or rootfs hosts that can't be connected to, for helpers running on
+    /// the guest's network
+    #[clap(long)]
+    pub check_network: bool,
+
+    /// Fetch the Ignition config and check that its spec version is
+    /// supported by the CoreOS release being installed
+    #[clap(long)]
+    pub check_ignition: bool,
+
+    /// Don't check that the Ignition config is JSON with an
+    /// `ignition.version` before punching
+    #[clap(long, conflicts_with = "check-ignition")]
+    pub no_validate_ignition: bool,
+
+    /// Spool the guest console to this file once the files are punched,
+    /// until the install completes or the console timeout expires. Needs
+    /// the helper to be the guest's secondary user or have CP class C.
+    #[clap(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = &["fleet", "restore-devices"]
+    )]
+    pub console_log: Option<PathBuf>,
+
+    /// Seconds to capture the guest console for
+    #[clap(long, value_name = "SECS", default_value = "1800")]
+    pub console_timeout: u64,
+
+    /// IPL the guest from its reader once the files are punched, instead
+    /// of leaving it to the operator. With --console-log the install must
+    /// then complete within the console timeout. Needs the helper to be
+    /// the guest's secondary user or have CP class C.
+    #[clap(long, conflicts_with = "fleet")]
+    pub auto_ipl: bool,
+
+    /// Keep the guest in the live system once installed
+    /// (coreos.inst.skip_reboot) to inspect it, the operator then IPLs it
+    /// from its install disk
+    #[clap(long, conflicts_with = "auto-ipl")]
+    pub no_reboot: bool,
+
+    /// Write the name, source URL, size and SHA256 of the staged images as
+    /// JSON to this file
+    #[clap(long, value_name = "PATH")]
+    pub manifest_out: Option<PathBuf>,
+
+    /// Remove downloaded images after a successful punch, they are kept
+    /// when the install fails so it can be retried
+    #[clap(long, conflicts_with = "keep-downloads")]
+    pub cleanup_downloads: bool,
+
+    /// Keep downloaded images, the default
+    #[clap(long)]
+    pub keep_downloads: bool,
+
+    /// Keep the punched parm file in the temporary directory for
+    /// inspection instead of removing it
+    #[clap(long)]
+    pub keep_parm: bool,
+
+    /// Directory the images are downloaded to and reused from, created if
+    /// missing, instead of the working directory
+    #[clap(long, value_name = "PATH", env = "ZVM_CACHE_DIR")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Record format of the punched parm file, the kernel and initrd are
+    /// always punched binary
+    #[clap(value_enum)]
+    #[clap(long, value_name = "FORMAT", default_value = "binary")]
+    pub punch_format: PunchFormat,
+
+    /// Compress an uncompressed cpio initrd before punching it, the kernel
+    /// detects the compression itself so the parm is unchanged. CoreOS live
+    /// initrds are already compressed and are punched as they are.
+    #[clap(value_enum)]
+    #[clap(long, value_name = "FORMAT")]
+    pub compress_initrd: Option<Compression>,
+
+    /// Install the guests listed in this JSON manifest, each entry sets
+    /// "zvm" and optionally "ip", "hostname", "nic" and a disk target
+    /// ("dasd", "edev", "scsi" or "mp"), everything else is taken from the
+    /// command line so the images are staged once for all guests
+    #[clap(long, value_name = "MANIFEST")]
+    pub fleet: Option<PathBuf>,
 
-    /// Guest nameserver= karg
-    #[clap(long, value_name = "NAMESERVER", default_value = "172.23.0.1")]
-    pub dns: Vec<String>,
+    /// Number of fleet guests punched at the same time
+    #[clap(long, value_name = "N", default_value = "1")]
+    pub fleet_parallel: usize,
+
+    /// Abort the whole install when it takes longer than this, such as
+    /// `90s`, `30m` or `1h30m`, a bare number is seconds. Running commands
+    /// are killed, the devices restored and partial downloads removed
+    #[clap(long, alias = "overall-timeout", value_name = "DURATION", value_parser = parse_duration)]
+    pub deadline: Option<Duration>,
+
+    /// Print the vmcp/vmur commands, the parm file and the files that
+    /// would be punched without running them, nothing is downloaded
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Only print errors and the final instructions
+    #[clap(long, short)]
+    pub quiet: bool,
+
+    /// Print the commands run and their exit status, -vv also traces the
+    /// transfers
+    #[clap(long, short, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Format of the end-of-run summary, with the resolved images, parm and
+    /// install disk, JSON also silences progress and --dry-run output
+    #[clap(value_enum)]
+    #[clap(long, value_name = "FORMAT", default_value = "human")]
+    pub output: OutputFormat,
 
     ///Images
     #[clap(subcommand)]
     pub images: Images,
 }
 
-#[derive(Debug, Parser)]
+/// Values of the `dfltcc=` karg
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Dfltcc {
+    /// Compression on level 1 and decompression, the kernel default
+    #[clap(alias = "true")]
+    On,
+    /// No hardware support
+    #[clap(alias = "false")]
+    Off,
+    /// Compression only
+    #[clap(name = "def_only")]
+    DefOnly,
+    /// Decompression only
+    #[clap(name = "inf_only")]
+    InfOnly,
+    /// Hardware compression on any level, for debugging
+    Always,
+}
+
+impl fmt::Display for Dfltcc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no skipped variants");
+        write!(f, "{}", value.get_name())
+    }
+}
+
+/// `vmur punch` record format
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum)]
+pub enum PunchFormat {
+    /// Fixed 80 byte records, padded with zeros
+    Binary,
+    /// ASCII to EBCDIC converted text records (`-t`)
+    Text,
+    /// Newline separated, blank padded records (`-b 0x25,0x40`)
+    Blocked,
+}
+
+/// Whether the installer must bring up the network
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum)]
+pub enum NeedNet {
+    Auto,
+    Yes,
+    No,
+}
+
+/// Kind of install disk
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum)]
+pub enum TargetKind {
+    /// ECKD DASD
+    Dasd,
+    /// FBA DASD
+    Edev,
+    /// zFCP SCSI LUN
+    Scsi,
+    /// zFCP paths grouped by dm-multipath
+    Multipath,
+}
+
+/// Compression of the punched initrd
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
+#[derive(Debug, Clone, Parser)]
+#[allow(clippy::enum_variant_names)]
 pub enum Images {
     /// Set live images
     LiveImages(Live),
 
     /// Set build artifacts
     Artifacts(Build),
+
+    /// Extract the live images from an OCI image
+    Oci(Oci),
 }
 
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser)]
+pub struct Oci {
+    /// Image reference, `docker://` is assumed without a transport
+    #[clap(value_name = "IMAGE_REF")]
+    pub image: String,
+}
+
+impl Oci {
+    /// Local names of the extracted live images, derived from the reference
+    pub fn live(&self) -> Live {
+        let name: String = self
+            .image
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let dir = image_dir().unwrap_or_default();
+        let url = |suffix| {
+            Url::from_file_path(dir.join(format!("{}-{}", name, suffix)))
+                .expect("image dir is absolute")
+        };
+        Live {
+            kernel: url("live-kernel-s390x"),
+            initrd: url("live-initramfs.s390x.img"),
+            rootfs: url("live-rootfs.s390x.img"),
+            kernel_sha256: None,
+            initrd_sha256: None,
+            mirrors: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
 pub struct Live {
     /// Base URL for kernel
-    #[clap(long, value_name = "VMLINUZ")]
+    #[clap(long, value_name = "VMLINUZ", value_parser = parse_image_url)]
     pub kernel: Url,
     /// Base URL for initrd
-    #[clap(long, value_name = "INITRD")]
+    #[clap(long, value_name = "INITRD", value_parser = parse_image_url)]
     pub initrd: Url,
     /// Base URL for rootfs
-    #[clap(long, value_name = "ROOTFS")]
+    #[clap(long, value_name = "ROOTFS", value_parser = parse_image_url)]
     pub rootfs: Url,
+    /// Expected SHA256 of the kernel
+    #[clap(long, value_name = "SHA256", value_parser = parse_sha256)]
+    pub kernel_sha256: Option<String>,
+    /// Expected SHA256 of the initrd
+    #[clap(long, value_name = "SHA256", value_parser = parse_sha256)]
+    pub initrd_sha256: Option<String>,
+    /// The same images on the --mirror builders, in order
+    #[clap(skip)]
+    pub mirrors: Vec<Live>,
 }
 
 #[derive(Debug, Clone, ValueEnum)]
@@ -135,11 +779,46 @@ pub enum CoreOS {
     RHCOS,
 }
 
-#[derive(Debug, Parser)]
+/// FCOS build flavor, selects the stream number in the version
+#[derive(Debug, Clone, ValueEnum)]
+pub enum BuildKind {
+    /// Developer build, `.dev.`
+    Dev,
+    /// Production `next` stream build, `.1.`
+    Next,
+    /// Production `testing` stream build, `.2.`
+    Testing,
+    /// Production `stable` stream build, `.3.`
+    Stable,
+}
+
+impl BuildKind {
+    fn infix(&self) -> &'static str {
+        match self {
+            Self::Dev => "dev",
+            Self::Next => "1",
+            Self::Testing => "2",
+            Self::Stable => "3",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
 pub struct Build {
     /// Base URL for builder
-    #[clap(long, value_name = "URL", default_value = "http://172.23.236.43")]
+    #[clap(
+        long,
+        value_name = "URL",
+        env = "ZVM_BUILDER_URL",
+        default_value = "http://172.23.236.43",
+        value_parser = parse_image_url
+    )]
     pub url: Url,
+    /// Builder mirroring --url, given once per mirror. The images are
+    /// downloaded from the mirrors in order when --url fails, the guest
+    /// still fetches the rootfs from --url.
+    #[clap(long, value_name = "URL", value_parser = parse_image_url)]
+    pub mirror: Vec<Url>,
     /// CoreOS variant
     #[clap(value_enum)]
     #[clap(long, value_name = "VARIANT", default_value = "fcos")]
@@ -147,31 +826,323 @@ pub struct Build {
     /// CoreOS version
     #[clap(long, value_name = "VERSION", default_value = "37")]
     pub version: String,
+    /// FCOS build kind
+    #[clap(value_enum)]
+    #[clap(long, value_name = "KIND", default_value = "dev")]
+    pub build_kind: BuildKind,
     /// Build date
     #[clap(long, value_name = "DATE")]
     pub date: Option<String>,
-    /// Build time
+    /// Build time, required for RHCOS without --ocp-version
     #[clap(long, value_name = "TIME")]
     pub time: Option<String>,
     /// Build id
     #[clap(long, value_name = "ID", default_value = "0")]
     pub id: u32,
+    /// FCOS stream (stable, testing or next) whose latest release is
+    /// installed, the other build options are only used when the stream
+    /// metadata can't be fetched
+    #[clap(long, value_name = "STREAM")]
+    pub stream: Option<String>,
+    /// OpenShift version (e.g. 4.13) whose RHCOS boot images are
+    /// installed, the other build options are only used when the release
+    /// metadata can't be fetched
+    #[clap(
+        long,
+        value_name = "VERSION",
+        conflicts_with = "stream",
+        value_parser = parse_ocp_version
+    )]
+    pub ocp_version: Option<String>,
+    /// Images of the latest --stream release
+    #[clap(skip)]
+    pub stream_images: Option<Box<Live>>,
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use clap::IntoApp;
+/// Parses an image location, plain paths are taken relative to the
+/// working directory and turned into `file://` URLs
+fn parse_image_url(s: &str) -> Result<Url, String> {
+    match Url::parse(s) {
+        Ok(url) => Ok(url),
+        Err(ParseError::RelativeUrlWithoutBase) => {
+            let path = current_dir()
+                .map_err(|e| format!("getting CWD: {}", e))?
+                .join(s);
+            Url::from_file_path(&path)
+                .map_err(|_| format!("'{}' is not a valid path", path.display()))
+        }
+        Err(e) => Err(format!("'{}' is not a valid URL: {}", s, e)),
+    }
+}
 
-    #[test]
-    fn clap_app() {
-        Cmd::command().debug_assert()
+/// Checks the static `client::gw:mask:...` form of `ip=`, other forms are
+/// passed through as is
+pub fn parse_ip(s: &str) -> Result<IpConfig, String> {
+    s.parse::<IpConfig>()
+        .map_err(|e| format!("invalid ip= '{}': {}", s, e))
+}
+
+/// Accepts only URLs the guest can fetch on its own: HTTP(S) on a host
+/// other than the local one
+fn parse_rootfs_url(s: &str) -> Result<Url, String> {
+    let url = Url::parse(s).map_err(|e| format!("'{}' is not a valid URL: {}", s, e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!(
+            "the guest can only fetch http(s) URLs, got '{}'",
+            s
+        ));
+    }
+    let loopback = match url.host() {
+        None => return Err(format!("'{}' has no host", s)),
+        Some(url::Host::Domain(domain)) => domain == "localhost",
+        Some(url::Host::Ipv4(ip)) => ip.is_loopback() || ip.is_unspecified(),
+        Some(url::Host::Ipv6(ip)) => ip.is_loopback() || ip.is_unspecified(),
+    };
+    if loopback {
+        return Err(format!("'{}' points at the guest itself", s));
+    }
+    Ok(url)
+}
+
+fn parse_ocp_version(s: &str) -> Result<String, String> {
+    match s.split_once('.') {
+        Some((major, minor))
+            if [major, minor]
+                .iter()
+                .all(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit())) =>
+        {
+            Ok(s.to_string())
+        }
+        _ => Err(format!("'{}' is not an OpenShift version as 4.13", s)),
+    }
+}
+
+/// Device number of a DASD as its `0.0.XXXX` bus id
+fn parse_bus_id(s: &str) -> Result<String, String> {
+    bus_id(s).map_err(|e| e.to_string())
+}
+
+/// zFCP path of a single LUN
+fn parse_scsi(s: &str) -> Result<String, String> {
+    let path = s.parse::<ZfcpPath>().map_err(|e| format!("{:#}", e))?;
+    match path.luns.len() {
+        1 => Ok(path.to_string()),
+        n => Err(format!("'{}' lists {} LUNs, expected a single one", s, n)),
+    }
+}
+
+/// --disk of any --target, a DASD device number or a zFCP path
+fn parse_disk(s: &str) -> Result<String, String> {
+    if s.contains(',') {
+        s.parse::<ZfcpPath>()
+            .map(|path| path.to_string())
+            .map_err(|e| format!("{:#}", e))
+    } else {
+        parse_bus_id(s)
+    }
+}
+
+fn parse_karg(s: &str) -> Result<String, String> {
+    if s.is_empty() || s.starts_with('=') || s.contains(char::is_whitespace) {
+        Err(format!("'{}' is not a karg, expected KEY=VALUE or KEY", s))
+    } else {
+        Ok(s.to_string())
+    }
+}
+
+/// A --dns address, IPv6 ones may be bracketed. None for an empty value.
+fn parse_nameserver(s: &str) -> Result<Option<IpAddr>, String> {
+    if s.is_empty() {
+        return Ok(None);
+    }
+    let addr = s
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .unwrap_or(s);
+    match addr.parse() {
+        Ok(IpAddr::V4(_)) if addr != s => Err(format!("'{}' is bracketed but not IPv6", s)),
+        Ok(ip) => Ok(Some(ip)),
+        Err(_) => Err(format!(
+            "'{}' is not a nameserver address, expected IPv4 or IPv6",
+            s
+        )),
+    }
+}
+
+fn parse_console(s: &str) -> Result<String, String> {
+    let device = s.split(',').next().unwrap_or_default();
+    if device.is_empty() || s.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+        Err(format!(
+            "'{}' is not a console, expected <device>[,<options>]",
+            s
+        ))
+    } else {
+        Ok(s.to_string())
+    }
+}
+
+/// Durations such as `45s`, `30m` or `1h30m`, a bare number is seconds
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("'{}' is not a duration such as 90s, 30m or 1h30m", s);
+    if let Ok(secs) = s.parse::<u64>() {
+        return match secs {
+            0 => Err(invalid()),
+            secs => Ok(Duration::from_secs(secs)),
+        };
+    }
+    let mut secs = 0u64;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let n: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+        let unit = match rest[digits..].chars().next() {
+            Some('s') => 1,
+            Some('m') => 60,
+            Some('h') => 3600,
+            Some('d') => 86400,
+            _ => return Err(invalid()),
+        };
+        secs = n
+            .checked_mul(unit)
+            .and_then(|n| secs.checked_add(n))
+            .ok_or_else(invalid)?;
+        rest = &rest[digits + 1..];
+    }
+    match secs {
+        0 => Err(invalid()),
+        secs => Ok(Duration::from_secs(secs)),
+    }
+}
+
+fn parse_basic_auth(s: &str) -> Result<Secret, String> {
+    match s.split_once(':') {
+        Some((user, _)) if !user.is_empty() => Ok(Secret(s.to_string())),
+        _ => Err("basic credentials must be in 'user:pass' form".to_string()),
+    }
+}
+
+fn parse_auth_host(s: &str) -> Result<AuthHost, String> {
+    let url = Url::parse(&format!("http://{}", s))
+        .ok()
+        .filter(|url| url.path() == "/" && url.username().is_empty())
+        .ok_or_else(|| format!("'{}' is not a HOST[:PORT]", s))?;
+    Ok(AuthHost {
+        host: url.host_str().unwrap_or_default().to_string(),
+        // the parser drops :80 as the http default
+        port: url.port().or_else(|| s.ends_with(":80").then_some(80)),
+    })
+}
+
+fn parse_sha256(s: &str) -> Result<String, String> {
+    if s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(s.to_ascii_lowercase())
+    } else {
+        Err(format!("'{}' is not a SHA256 digest of 64 hex digits", s))
+    }
+}
+
+fn parse_spool_class(s: &str) -> Result<char, String> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphanumeric() => Ok(c.to_ascii_uppercase()),
+        _ => Err(format!(
+            "'{}' is not a spool class, expected a single character A-Z or 0-9",
+            s
+        )),
+    }
+}
+
+fn parse_ssh_host(s: &str) -> Result<String, String> {
+    if s.is_empty() || s.starts_with('-') || s.contains(|c: char| c.is_whitespace() || c == ':') {
+        Err(format!(
+            "'{}' is not an SSH destination, expected [<user>@]<host>",
+            s
+        ))
+    } else {
+        Ok(s.to_string())
+    }
+}
+
+fn parse_spool_name(s: &str) -> Result<String, String> {
+    let part = |p: &str| {
+        (1..=8).contains(&p.len())
+            && p.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "@#$+-_".contains(c))
+    };
+    match s.split_once('.') {
+        Some((name, kind)) if part(name) && part(kind) => Ok(s.to_string()),
+        _ => Err(format!(
+            "'{}' is not a spool file name, expected <name>.<type> of up to 8 characters each",
+            s
+        )),
+    }
+}
+
+/// String which never shows up in `Debug` or `Display` output
+#[derive(Clone)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for Secret {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<redacted>")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<redacted>")
+    }
+}
+
+/// Host, and port if given, the `--http-auth-*` credentials are sent to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthHost {
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl AuthHost {
+    /// Host and port of `url`
+    pub fn of(url: &Url) -> Option<Self> {
+        Some(Self {
+            host: url.host_str()?.to_string(),
+            port: url.port_or_known_default(),
+        })
+    }
+
+    /// Whether requests to `url` get the credentials
+    pub fn matches(&self, url: &Url) -> bool {
+        url.host_str() == Some(self.host.as_str())
+            && self
+                .port
+                .is_none_or(|port| url.port_or_known_default() == Some(port))
     }
 }
 
-impl From<&Build> for Live {
-    fn from(images: &Build) -> Self {
-        let generate = |image: &str| {
+impl TryFrom<&Build> for Live {
+    type Error = anyhow::Error;
+
+    fn try_from(images: &Build) -> anyhow::Result<Self> {
+        if let Some(live) = &images.stream_images {
+            return Ok(live.as_ref().clone());
+        }
+        let generate = |base: &Url, image: &str| {
             let date = match images.date.as_ref() {
                 Some(v) => Cow::from(v),
                 _ => {
@@ -181,10 +1152,15 @@ impl From<&Build> for Live {
             };
             let name = match images.variant {
                 // fedora-coreos-37.20230314.dev.0-live-
+                // fedora-coreos-37.20230322.3.0-live-
                 CoreOS::FCOS => {
                     format!(
-                        "fedora-coreos-{}.{}.dev.{}-live-{}",
-                        images.version, date, images.id, image
+                        "fedora-coreos-{}.{}.{}.{}-live-{}",
+                        images.version,
+                        date,
+                        images.build_kind.infix(),
+                        images.id,
+                        image
                     )
                 }
                 // rhcos-413.92.202303141019-0-live-
@@ -201,50 +1177,517 @@ impl From<&Build> for Live {
                     )
                 }
             };
-            if images.url.scheme() == "http" {
-                images
-                    .url
-                    .join(&name)
-                    .with_context(|| format!("joining '{}' '{}'", images.url, name))
+            if matches!(base.scheme(), "http" | "https") {
+                base.join(&name)
+                    .with_context(|| format!("joining '{}' '{}'", base, name))
             } else {
-                let path = current_dir().context("CWD")?.join(name);
+                let dir = match base.scheme() {
+                    "file" => base
+                        .to_file_path()
+                        .map_err(|_| anyhow!("'{}' is not a valid local path", base))?,
+                    _ => image_dir()?,
+                };
+                let path = dir.join(name);
                 match Url::from_file_path(&path) {
                     Ok(url) => Ok(url),
                     _ => Err(anyhow!("Building URL from {:?}", path)),
                 }
             }
         };
-        Live {
-            kernel: generate("kernel-s390x").unwrap(),
-            initrd: generate("initramfs.s390x.img").unwrap(),
-            rootfs: generate("rootfs.s390x.img").unwrap(),
+        let live = |base: &Url| -> anyhow::Result<Live> {
+            Ok(Live {
+                kernel: generate(base, "kernel-s390x")?,
+                initrd: generate(base, "initramfs.s390x.img")?,
+                rootfs: generate(base, "rootfs.s390x.img")?,
+                kernel_sha256: None,
+                initrd_sha256: None,
+                mirrors: Vec::new(),
+            })
+        };
+        let mut primary = live(&images.url)?;
+        primary.mirrors = images
+            .mirror
+            .iter()
+            .map(live)
+            .collect::<anyhow::Result<_>>()?;
+        Ok(primary)
+    }
+}
+
+impl Images {
+    /// Live images URLs, generated from the build if needed
+    pub fn live(&self) -> anyhow::Result<Live> {
+        match self {
+            Self::LiveImages(live) => Ok(live.clone()),
+            Self::Artifacts(build) => Live::try_from(build),
+            Self::Oci(oci) => Ok(oci.live()),
         }
     }
 }
 
+impl InstallConfig {
+    /// Whether progress messages should be printed
+    pub fn progress(&self) -> bool {
+        !self.quiet && self.output == OutputFormat::Human
+    }
+
+    /// Install disk, from --target/--disk or the --dasd, --edev, --scsi
+    /// and --mp shorthands
+    pub fn disk(&self) -> anyhow::Result<Option<DiskConfig>> {
+        if self.mp_name.is_some() && self.mp.is_none() && self.target != Some(TargetKind::Multipath)
+        {
+            bail!("--mp-name needs a multipath install disk");
+        }
+        let single = |kind| match self.disk.as_slice() {
+            [disk] => Ok(disk.clone()),
+            disks => Err(anyhow!(
+                "--target {} takes a single --disk, got {}",
+                kind,
+                disks.len()
+            )),
+        };
+        let disk = match &self.target {
+            Some(TargetKind::Dasd) => DiskConfig::Dasd {
+                devno: bus_id(&single("dasd")?).context("invalid --disk")?,
+            },
+            Some(TargetKind::Edev) => DiskConfig::Edev {
+                devno: bus_id(&single("edev")?).context("invalid --disk")?,
+            },
+            Some(TargetKind::Scsi) => DiskConfig::Scsi {
+                path: parse_scsi(&single("scsi")?).map_err(|e| anyhow!("invalid --disk: {}", e))?,
+            },
+            Some(TargetKind::Multipath) => {
+                let paths = self
+                    .disk
+                    .iter()
+                    .map(|p| p.parse())
+                    .collect::<anyhow::Result<Vec<ZfcpPath>>>()?;
+                DiskConfig::Multipath(MultipathDisks::new(paths).context("invalid --disk")?)
+            }
+            None => {
+                // parsed again for fleet manifests, which bypass clap
+                if let Some(devno) = &self.dasd {
+                    DiskConfig::Dasd {
+                        devno: bus_id(devno).context("invalid --dasd")?,
+                    }
+                } else if let Some(devno) = &self.edev {
+                    DiskConfig::Edev {
+                        devno: bus_id(devno).context("invalid --edev")?,
+                    }
+                } else if let Some(path) = &self.scsi {
+                    DiskConfig::Scsi {
+                        path: parse_scsi(path).map_err(|e| anyhow!("invalid --scsi: {}", e))?,
+                    }
+                } else if let Some(mp) = &self.mp {
+                    DiskConfig::Multipath(MultipathDisks::new(mp.clone()).context("invalid --mp")?)
+                } else {
+                    return Ok(None);
+                }
+            }
+        };
+        match (disk, &self.mp_name) {
+            (DiskConfig::Multipath(mp), Some(name)) => Ok(Some(DiskConfig::Multipath(
+                mp.named(name).context("invalid --mp-name")?,
+            ))),
+            (disk, _) => Ok(Some(disk)),
+        }
+    }
+
+    /// Checks what clap can't check on its own, failing with
+    /// [`Error::InvalidConfig`]
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.check()
+            .map_err(|e| Error::InvalidConfig(format!("{:#}", e)).into())
+    }
+
+    fn check(&self) -> anyhow::Result<()> {
+        self.disk()?;
+        if let Some(path) = &self.ca_cert {
+            ca_certificates(path)?;
+        }
+        let local = self.ignition_path();
+        if let Some(path) = &local {
+            if !path.is_file() {
+                bail!("Ignition config '{}' does not exist", path.display());
+            }
+        }
+        if self.ignition_serve.is_some()
+            && self.ignition_file.is_empty()
+            && !self.ignition_additions()
+            && local.is_none()
+        {
+            bail!("--ignition-serve needs a local --ignition, --ignition-file, --ssh-key or --luks config to serve");
+        }
+        if self.ignition_additions() && self.ignition_file.is_empty() && local.is_none() {
+            if self.ignition_serve.is_none() {
+                bail!("--ssh-key or --luks without --ignition-file needs --ignition-serve, the served config merges --ignition");
+            }
+            if self.ignition_source().is_empty() {
+                bail!("--ssh-key or --luks without --ignition-file needs the --ignition config to merge");
+            }
+        }
+        if !self.insecure {
+            let rootfs = match &self.rootfs_url {
+                Some(url) => Some(url.to_string()),
+                None => self.images.live().ok().map(|live| live.rootfs.to_string()),
+            };
+            let urls = [
+                ("--ignition", Some(self.ignition.clone())),
+                ("rootfs", rootfs),
+            ];
+            for (what, url) in urls {
+                if let Some(url) = url.filter(|url| url.starts_with("http://")) {
+                    warn!(
+                        "--insecure=no but the {} {} is fetched over plain http",
+                        what, url
+                    );
+                }
+            }
+        }
+        let rootfs = match &self.rootfs_url {
+            Some(url) => Some(url.clone()),
+            None => self.images.live().ok().map(|live| live.rootfs),
+        };
+        if let Some(url) = rootfs {
+            if let Some(format) = compressed_name(&url) {
+                warn!(
+                    "the guest fetches the {} compressed rootfs {} itself and can't boot from it, --rootfs-url should point to a decompressed copy",
+                    format, url
+                );
+            }
+        }
+        if self.luks && self.tang_url.is_empty() {
+            match self.tpm2 {
+                true => bail!(
+                    "z/VM guests have no TPM2 device, --tpm2 needs a --tang-url to unlock the root"
+                ),
+                false => bail!("--luks needs a key source, --tang-url or --tpm2"),
+            }
+        }
+        if self.tang_thumbprint.len() != self.tang_url.len() {
+            bail!(
+                "{} --tang-thumbprint for {} --tang-url, each server needs one",
+                self.tang_thumbprint.len(),
+                self.tang_url.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Where the --http-auth-* credentials go: --http-auth-host, else the
+    /// builder --url or the host of the live kernel
+    pub fn auth_host(&self) -> Option<AuthHost> {
+        if let Some(host) = &self.http_auth_host {
+            return Some(host.clone());
+        }
+        match &self.images {
+            Images::Artifacts(build) => AuthHost::of(&build.url),
+            Images::LiveImages(live) => AuthHost::of(&live.kernel),
+            Images::Oci(_) => None,
+        }
+    }
+
+    /// Whether --ssh-key or --luks add to the Ignition config
+    pub fn ignition_additions(&self) -> bool {
+        !self.ssh_key.is_empty() || self.luks
+    }
+
+    /// The --ignition config as given, not the served one
+    pub fn ignition_source(&self) -> &str {
+        self.ignition_source.as_deref().unwrap_or(&self.ignition)
+    }
+
+    /// Local file of the --ignition config, given as a path or file:// URL
+    pub fn ignition_path(&self) -> Option<PathBuf> {
+        let source = self.ignition_source();
+        match Url::parse(source) {
+            Ok(url) if url.scheme() == "file" => url.to_file_path().ok(),
+            Ok(_) => None,
+            Err(_) if source.is_empty() => None,
+            Err(_) => Some(PathBuf::from(source)),
+        }
+    }
+
+    /// Where the Ignition config is served, --ignition-serve or an
+    /// ephemeral port for a local --ignition
+    pub fn serve_addr(&self) -> Option<ServeAddr> {
+        match (&self.ignition_serve, self.ignition_path()) {
+            (Some(addr), _) => Some(addr.clone()),
+            (None, Some(_)) => Some(ServeAddr { ip: None, port: 0 }),
+            (None, None) => None,
+        }
+    }
+
+    /// First static --ip, the one the guest is reached through
+    pub fn static_ip(&self) -> Option<&NetworkConfig> {
+        if self.dhcp {
+            return None;
+        }
+        self.ip.iter().find_map(IpConfig::static_config)
+    }
+
+    /// --dns, or the default nameserver of a static network
+    pub fn nameservers(&self) -> Vec<IpAddr> {
+        match self.static_ip() {
+            Some(_) if self.dns.is_empty() => vec![DEFAULT_DNS],
+            _ => self.dns.iter().flatten().copied().collect(),
+        }
+    }
+
+    /// --karg values in order, the last of each key
+    pub fn kargs(&self) -> Vec<&str> {
+        let key = |karg: &str| karg.split('=').next().unwrap_or_default().to_string();
+        let mut kargs: Vec<&str> = Vec::new();
+        for karg in &self.karg {
+            if let Some(n) = kargs.iter().position(|k| key(k) == key(karg)) {
+                warn!("--karg {} overrides --karg {}", karg, kargs[n]);
+                kargs.remove(n);
+            }
+            kargs.push(karg);
+        }
+        kargs
+    }
+
+    /// Values of the `ip=` kargs, one per --znet, with --hostname and
+    /// --nic applied to the first one. Without --nic, a predictable ccw
+    /// interface name of a static --ip follows its --znet device.
+    pub fn ip_kargs(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self.interfaces()?.iter().map(<_>::to_string).collect())
+    }
+
+    /// `rd.znet=` of every device, each followed by the `ip=` of its
+    /// interface unless bonded, then the `ip=` of the --bond with its
+    /// `bond=` and the `vlan=` of --vlan
+    pub fn network_kargs(&self) -> anyhow::Result<Vec<String>> {
+        let mut ips = self.ip_kargs()?.into_iter();
+        let bond = self.bond.as_ref().and_then(|_| ips.next());
+        let mut kargs = Vec::new();
+        for znet in &self.znet {
+            kargs.push(format!("rd.znet={}", znet));
+            if !self.bonded(znet) {
+                kargs.extend(ips.next().map(|ip| format!("ip={}", ip)));
+            }
+        }
+        if let (Some(bond), Some(ip)) = (&self.bond, bond) {
+            kargs.push(format!("ip={}", ip));
+            kargs.push(format!("bond={}", bond));
+        }
+        kargs.extend(self.vlan_karg()?);
+        Ok(kargs)
+    }
+
+    /// `vlan=<nic>.<id>:<nic>` karg of --vlan on the first interface
+    pub fn vlan_karg(&self) -> anyhow::Result<Option<String>> {
+        let Some(id) = self.vlan else {
+            return Ok(None);
+        };
+        let vlan = match self.interfaces()?.first() {
+            Some(IpConfig::Static(NetworkConfig { nic: Some(nic), .. }))
+            | Some(IpConfig::Interface { nic, .. }) => nic.clone(),
+            _ => bail!("--vlan needs an interface name, pass --nic"),
+        };
+        let parent = vlan.strip_suffix(&format!(".{}", id)).unwrap_or(&vlan);
+        Ok(Some(format!("vlan={}:{}", vlan, parent)))
+    }
+
+    /// Whether the interface of a --znet device is a --bond slave
+    fn bonded(&self, znet: &str) -> bool {
+        match (&self.bond, znet_iface(znet)) {
+            (Some(bond), Some(nic)) => bond.slaves.contains(&nic),
+            _ => false,
+        }
+    }
+
+    /// Configuration of each interface: the --bond, then the --znet
+    /// devices outside of it, the first one on its --vlan if given
+    fn interfaces(&self) -> anyhow::Result<Vec<IpConfig>> {
+        let mut slots: Vec<Option<&String>> = Vec::new();
+        if let Some(bond) = &self.bond {
+            for slave in &bond.slaves {
+                if !self
+                    .znet
+                    .iter()
+                    .any(|znet| znet_iface(znet).as_ref() == Some(slave))
+                {
+                    bail!("bond slave '{}' is not the interface of a --znet", slave);
+                }
+            }
+            slots.push(None);
+        }
+        slots.extend(self.znet.iter().filter(|znet| !self.bonded(znet)).map(Some));
+        let default_nic = |znet: Option<&String>| match (znet, &self.bond) {
+            (Some(znet), _) => znet_iface(znet),
+            (None, bond) => bond.as_ref().map(|bond| bond.name.clone()),
+        };
+        let mut interfaces = if self.dhcp {
+            if self.hostname.is_some() {
+                bail!("--hostname requires a static --ip, DHCP assigns it with --dhcp");
+            }
+            slots
+                .iter()
+                .enumerate()
+                .map(|(n, znet)| {
+                    let nic = match n {
+                        0 => self.nic.clone().or_else(|| default_nic(*znet)),
+                        _ => default_nic(*znet),
+                    };
+                    match nic {
+                        Some(nic) => IpConfig::Interface {
+                            nic,
+                            autoconf: "dhcp".to_string(),
+                            extra: Vec::new(),
+                        },
+                        None => IpConfig::Auto("dhcp".to_string()),
+                    }
+                })
+                .collect()
+        } else {
+            if self.ip.len() != slots.len() {
+                match self.bond {
+                    Some(_) => bail!(
+                        "{} --ip for {} interfaces, the --bond and each --znet outside of it need one",
+                        self.ip.len(),
+                        slots.len()
+                    ),
+                    None => bail!(
+                        "{} --ip for {} --znet, each interface needs both",
+                        self.ip.len(),
+                        self.znet.len()
+                    ),
+                }
+            }
+            self.ip
+                .iter()
+                .zip(&slots)
+                .enumerate()
+                .map(|(n, (ip, znet))| {
+                    let (hostname, nic) = match n {
+                        0 => (self.hostname.as_ref(), self.nic.as_ref()),
+                        _ => (None, None),
+                    };
+                    self.ip_config(ip, default_nic(*znet), hostname, nic)
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?
+        };
+        if let (Some(id), Some(first)) = (self.vlan, interfaces.first_mut()) {
+            let tag = |nic: &mut String| *nic = format!("{}.{}", nic, id);
+            match first {
+                IpConfig::Static(NetworkConfig { nic: Some(nic), .. })
+                | IpConfig::Interface { nic, .. } => tag(nic),
+                IpConfig::Static(_) => bail!("--vlan needs an interface name, pass --nic"),
+                IpConfig::Auto(autoconf) => {
+                    let Some(mut nic) = default_nic(slots[0]) else {
+                        bail!("--vlan needs an interface name, pass --nic");
+                    };
+                    tag(&mut nic);
+                    *first = IpConfig::Interface {
+                        nic,
+                        autoconf: autoconf.clone(),
+                        extra: Vec::new(),
+                    };
+                }
+            }
+        }
+        if self.mtu.len() > 1 && self.mtu.len() != interfaces.len() {
+            bail!(
+                "{} --mtu for {} interfaces, give one for all of them or one each",
+                self.mtu.len(),
+                interfaces.len()
+            );
+        }
+        let mtus = self.mtu.iter().cycle().take(interfaces.len());
+        for (n, (interface, mtu)) in interfaces.iter_mut().zip(mtus).enumerate() {
+            let extra = match interface {
+                IpConfig::Static(net) => &mut net.extra,
+                IpConfig::Interface { extra, .. } => extra,
+                IpConfig::Auto(autoconf) => {
+                    let Some(nic) = default_nic(slots[n]) else {
+                        bail!("--mtu needs an interface name, pass --nic");
+                    };
+                    *interface = IpConfig::Interface {
+                        nic,
+                        autoconf: autoconf.clone(),
+                        extra: Vec::new(),
+                    };
+                    let IpConfig::Interface { extra, .. } = interface else {
+                        unreachable!()
+                    };
+                    extra
+                }
+            };
+            match extra.first_mut() {
+                None => extra.push(mtu.to_string()),
+                Some(dns) if dns.parse::<IpAddr>().is_ok() => {
+                    bail!("--mtu conflicts with the nameserver fields of --ip")
+                }
+                Some(field) => *field = mtu.to_string(),
+            }
+        }
+        Ok(interfaces)
+    }
+
+    /// Configuration of an interface, `default_nic` is its predictable
+    /// name
+    fn ip_config(
+        &self,
+        ip: &IpConfig,
+        default_nic: Option<String>,
+        hostname: Option<&String>,
+        nic: Option<&String>,
+    ) -> anyhow::Result<IpConfig> {
+        let Some(net) = ip.static_config() else {
+            if hostname.is_some() || nic.is_some() {
+                bail!("--hostname and --nic require a static --ip, got '{}'", ip);
+            }
+            return Ok(ip.clone());
+        };
+        if let (Some(addr), false) = (net.mixed_families().first(), self.allow_mixed_ip_families) {
+            bail!(
+                "{} is not an {} address like the client address {} of --ip, pass --allow-mixed-ip-families if intended",
+                addr,
+                if net.ip.is_ipv6() { "IPv6" } else { "IPv4" },
+                net.ip
+            );
+        }
+        let mut net = net.clone();
+        net.nic = match nic {
+            Some(nic) => Some(nic.clone()),
+            None if net.nic.as_deref().is_none_or(|n| n.starts_with("enc")) => {
+                default_nic.or_else(|| net.nic.clone())
+            }
+            None => net.nic.clone(),
+        };
+        if let Some(hostname) = hostname {
+            net.hostname = Some(hostname.clone());
+        }
+        Ok(IpConfig::Static(net))
+    }
+}
+
 impl fmt::Display for InstallConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
             "Installing CoreOS:\nzVM:\t{}\nIP:\t{}\n{}\n",
-            self.zvm, self.ip, self.images
+            self.zvm,
+            match self.dhcp {
+                true => "dhcp".to_string(),
+                false => self
+                    .ip
+                    .iter()
+                    .map(<_>::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            },
+            self.images
         )?;
         write!(
             f,
             "Ignition:\t{}\ndfltcc:\t{:?}\nCmdline:\t{:?}",
             self.ignition, self.dfltcc, self.cmdline
         )?;
-        if let Some(dasd) = self.dasd.as_ref() {
-            write!(f, "Target:\n\tECKD-DASD: {}\n", dasd)?;
-        }
-        if let Some(edev) = self.edev.as_ref() {
-            write!(f, "Target:\n\tEDEV-DASD(FBA): {}\n", edev)?;
-        }
-        if let Some(scsi) = self.scsi.as_ref() {
-            write!(f, "Target:\n\tzFCP: {}\n", scsi)?;
-        }
-        if let Some(mp) = self.mp.as_ref() {
-            write!(f, "Target:\n\tMultipath: {:?}\n", mp)?;
+        if let Ok(Some(disk)) = self.disk() {
+            write!(f, "Target:\n\t{}\n", disk)?;
         }
         Ok(())
     }
@@ -264,7 +1707,602 @@ impl fmt::Display for Images {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::LiveImages(images) => images.fmt(f),
-            Self::Artifacts(build) => Live::from(build).fmt(f),
+            Self::Artifacts(build) => match Live::try_from(build) {
+                Ok(live) => live.fmt(f),
+                Err(e) => write!(f, "Build: {:#}", e),
+            },
+            Self::Oci(oci) => write!(f, "OCI: {}\n{}", oci.image, oci.live()),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use clap::IntoApp;
+
+    /// `install -i http://ign <extra> artifacts`
+    pub(crate) fn install(extra: &[&str]) -> Result<InstallConfig, clap::Error> {
+        install_with("http://ign", extra, &[])
+    }
+
+    /// `install -i <ignition> <extra> artifacts <build>`
+    pub(crate) fn install_with(
+        ignition: &str,
+        extra: &[&str],
+        build: &[&str],
+    ) -> Result<InstallConfig, clap::Error> {
+        let args = ["zvmhelper", "install", "-i", ignition];
+        let artifacts = args.iter().chain(extra).chain(&["artifacts"]);
+        Cmd::try_parse_from(artifacts.chain(build)).map(|cmd| cmd.config())
+    }
+
+    /// Dev build 37.20230314 of FCOS at `url`
+    fn build(url: &str) -> Build {
+        Build {
+            url: parse_image_url(url).unwrap(),
+            mirror: Vec::new(),
+            variant: CoreOS::FCOS,
+            version: "37".into(),
+            build_kind: BuildKind::Dev,
+            date: Some("20230314".into()),
+            time: None,
+            id: 0,
+            stream: None,
+            ocp_version: None,
+            stream_images: None,
         }
     }
+
+    #[test]
+    fn clap_app() {
+        Cmd::command().debug_assert()
+    }
+
+    #[test]
+    fn shell_completions() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let mut script = Vec::new();
+            Cmd::completions(shell, &mut script);
+            let script = String::from_utf8(script).unwrap();
+            for word in [
+                "install",
+                "verify",
+                "parm",
+                "live-images",
+                "artifacts",
+                "znet",
+                "mp-name",
+            ] {
+                assert!(script.contains(word), "{} has no {}", shell, word);
+            }
+        }
+        let cmd = Cmd::try_parse_from(["zvmhelper", "completions", "zsh"]).unwrap();
+        assert!(cmd.options().is_none());
+        assert!(Cmd::try_parse_from(["zvmhelper", "completions", "tcsh"]).is_err());
+    }
+
+    #[test]
+    fn rhcos_requires_time_or_ocp_version() {
+        let resolve = |build: &[&str]| {
+            crate::stream::resolve(&mut install_with("http://ign", &[], build).unwrap())
+        };
+        let err = resolve(&["--variant", "rhcos"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "RHCOS artifacts require --time or --ocp-version"
+        );
+
+        assert!(resolve(&["--variant", "rhcos", "--time", "1019"]).is_ok());
+        assert!(resolve(&[]).is_ok());
+
+        let err = resolve(&["--ocp-version", "4.13"]).unwrap_err();
+        assert_eq!(err.to_string(), "--ocp-version is only available for RHCOS");
+        let ocp = [
+            "--variant",
+            "rhcos",
+            "--ocp-version",
+            "4.13",
+            "--stream",
+            "x",
+        ];
+        assert!(install_with("http://ign", &[], &ocp).is_err());
+        let ocp = ["--variant", "rhcos", "--ocp-version", "4.13.1"];
+        assert!(install_with("http://ign", &[], &ocp).is_err());
+    }
+
+    #[test]
+    fn image_paths() {
+        let url = parse_image_url("my images/kernel").unwrap();
+        assert_eq!(url.scheme(), "file");
+        assert!(url.path().ends_with("/my%20images/kernel"));
+        assert_eq!(
+            crate::images::local_path(&url).unwrap(),
+            current_dir().unwrap().join("my images/kernel")
+        );
+
+        let url = parse_image_url("/srv/my images/kernel").unwrap();
+        assert_eq!(url.as_str(), "file:///srv/my%20images/kernel");
+        assert!(parse_image_url("http://[::1").is_err());
+    }
+
+    #[test]
+    fn build_from_local_dir() {
+        let build = build("/srv/my images");
+        assert_eq!(
+            crate::images::local_path(&Live::try_from(&build).unwrap().kernel).unwrap(),
+            std::path::Path::new(
+                "/srv/my images/fedora-coreos-37.20230314.dev.0-live-kernel-s390x"
+            )
+        );
+    }
+
+    #[test]
+    fn rhcos_build_without_time() {
+        let mut build = build("http://builder");
+        build.variant = CoreOS::RHCOS;
+        build.version = "413".into();
+        let err = Live::try_from(&build).unwrap_err();
+        assert_eq!(err.to_string(), "RHCOS artifacts require build time");
+
+        build.time = Some("1019".into());
+        assert_eq!(
+            Live::try_from(&build).unwrap().kernel.as_str(),
+            "http://builder/rhcos-413.202303141019-0-live-kernel-s390x"
+        );
+    }
+
+    #[test]
+    fn ip_gateway_and_mask() {
+        let ip = "172.23.237.227::172.23.0.1:255.255.0.0:coreos:encbdf0:none";
+        assert_eq!(parse_ip(ip).unwrap().to_string(), ip);
+        assert!(parse_ip("172.23.237.227::172.23.0.1:16:coreos:encbdf0:none").is_ok());
+        assert!(parse_ip("dhcp").is_ok());
+
+        let err = parse_ip("172.23.237.227::172.24.0.1:255.255.0.0:coreos:encbdf0:none");
+        assert_eq!(
+            err.unwrap_err(),
+            "invalid ip= '172.23.237.227::172.24.0.1:255.255.0.0:coreos:encbdf0:none': \
+gateway 172.24.0.1 is outside of the 172.23.0.0/16 network"
+        );
+        let err = parse_ip("172.23.237.227::172.23.0.1:255.0.255.0:coreos:encbdf0:none");
+        assert!(err
+            .unwrap_err()
+            .ends_with(": netmask '255.0.255.0' is not contiguous"));
+        assert!(parse_ip("172.23.237.227::172.23.0.1:33:coreos:encbdf0:none").is_err());
+    }
+
+    #[test]
+    fn ip_hostname_and_nic() {
+        let cfg = install(&["--hostname", "guest1", "--nic", "enc600"]).unwrap();
+        assert_eq!(
+            cfg.ip_kargs().unwrap(),
+            ["172.23.237.227::172.23.0.1:255.255.0.0:guest1:enc600:none"]
+        );
+
+        let cfg = install(&["--ip", "dhcp", "--nic", "enc600"]).unwrap();
+        assert!(cfg.ip_kargs().is_err());
+    }
+
+    #[test]
+    fn dhcp_guest() {
+        let cfg = install(&[
+            "--dhcp",
+            "--znet",
+            "qeth,0.0.bdf0,0.0.bdf1,0.0.bdf2",
+            "--znet",
+            "qeth,0.0.0600,0.0.0601,0.0.0602",
+        ])
+        .unwrap();
+        assert_eq!(cfg.ip_kargs().unwrap(), ["encbdf0:dhcp", "enc600:dhcp"]);
+        assert!(cfg.static_ip().is_none());
+        assert!(cfg.nameservers().is_empty());
+
+        let cfg = install(&["--dhcp", "--nic", "eth0", "--dns", "10.0.0.53"]).unwrap();
+        assert_eq!(cfg.ip_kargs().unwrap(), ["eth0:dhcp"]);
+        assert_eq!(cfg.nameservers(), ["10.0.0.53".parse::<IpAddr>().unwrap()]);
+        assert!(install(&["--dhcp", "--hostname", "guest1"])
+            .unwrap()
+            .ip_kargs()
+            .is_err());
+
+        let err =
+            install(&["--dhcp", "--ip", "10.0.0.2:::255.255.255.0::enc600:none"]).unwrap_err();
+        assert_eq!(err.kind(), clap::ErrorKind::ArgumentConflict);
+
+        assert!(install(&["--ip", "dhcp"]).unwrap().nameservers().is_empty());
+        assert_eq!(install(&[]).unwrap().nameservers(), [DEFAULT_DNS]);
+    }
+
+    #[test]
+    fn nameservers() {
+        let cfg = install(&["--dns", "10.0.0.1,fd00::53", "--dns", "[fd00::54]"]).unwrap();
+        let expected: Vec<IpAddr> = ["10.0.0.1", "fd00::53", "fd00::54"]
+            .iter()
+            .map(|ns| ns.parse().unwrap())
+            .collect();
+        assert_eq!(cfg.nameservers(), expected);
+        assert!(install(&["--dns", ""]).unwrap().nameservers().is_empty());
+
+        let err = install(&["--dns", "10.0.0.1,10.0.0.256"]).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("'10.0.0.256' is not a nameserver address"),
+            "{}",
+            err
+        );
+        assert!(install(&["--dns", "[10.0.0.1]"]).is_err());
+        assert!(install(&["--dns", "dns.example.com"]).is_err());
+    }
+
+    #[test]
+    fn vlan_guest() {
+        let cfg = install(&["--vlan", "100"]).unwrap();
+        assert_eq!(
+            cfg.ip_kargs().unwrap(),
+            ["172.23.237.227::172.23.0.1:255.255.0.0:coreos:encbdf0.100:none"]
+        );
+        assert_eq!(
+            cfg.vlan_karg().unwrap().unwrap(),
+            "vlan=encbdf0.100:encbdf0"
+        );
+
+        let cfg = install(&["--vlan", "4094", "--dhcp", "--nic", "eth0"]).unwrap();
+        assert_eq!(cfg.ip_kargs().unwrap(), ["eth0.4094:dhcp"]);
+        assert_eq!(cfg.vlan_karg().unwrap().unwrap(), "vlan=eth0.4094:eth0");
+
+        let cfg = install(&["--vlan", "7", "--ip", "dhcp"]).unwrap();
+        assert_eq!(cfg.ip_kargs().unwrap(), ["encbdf0.7:dhcp"]);
+
+        let cfg = install(&["--vlan", "7", "--ip", "dhcp", "--znet", "qeth"]).unwrap();
+        assert!(cfg.vlan_karg().is_err());
+
+        assert!(install(&[]).unwrap().vlan_karg().unwrap().is_none());
+        for id in ["0", "4095", "x"] {
+            assert!(install(&["--vlan", id]).is_err(), "{}", id);
+        }
+    }
+
+    #[test]
+    fn mtu_guest() {
+        let cfg = install(&["--mtu", "9000"]).unwrap();
+        assert_eq!(
+            cfg.ip_kargs().unwrap(),
+            ["172.23.237.227::172.23.0.1:255.255.0.0:coreos:encbdf0:none:9000"]
+        );
+        let cfg = install(&["--mtu", "1500", "--dhcp", "--vlan", "7"]).unwrap();
+        assert_eq!(cfg.ip_kargs().unwrap(), ["encbdf0.7:dhcp:1500"]);
+        let cfg = install(&["--mtu", "9000", "--ip", "eth0:dhcp:1500:aa:bb:cc:dd:ee:ff"]).unwrap();
+        assert_eq!(
+            cfg.ip_kargs().unwrap(),
+            ["eth0:dhcp:9000:aa:bb:cc:dd:ee:ff"]
+        );
+
+        let two = [
+            "--znet",
+            "qeth,0.0.bdf0,0.0.bdf1,0.0.bdf2",
+            "--znet",
+            "qeth,0.0.0600,0.0.0601,0.0.0602",
+            "--ip",
+            "dhcp",
+            "--ip",
+            "dhcp",
+        ];
+        let cfg = install(&[&two[..], &["--mtu", "9000"]].concat()).unwrap();
+        assert_eq!(
+            cfg.ip_kargs().unwrap(),
+            ["encbdf0:dhcp:9000", "enc600:dhcp:9000"]
+        );
+        let cfg = install(&[&two[..], &["--mtu", "9000", "--mtu", "1500"]].concat()).unwrap();
+        assert_eq!(
+            cfg.ip_kargs().unwrap(),
+            ["encbdf0:dhcp:9000", "enc600:dhcp:1500"]
+        );
+        let cfg = install(&["--mtu", "9000", "--mtu", "1500"]).unwrap();
+        assert!(cfg.ip_kargs().is_err());
+
+        let cfg = install(&[
+            "--mtu",
+            "9000",
+            "--ip",
+            "10.0.0.2::10.0.0.1:24::eth0:none:10.0.0.1",
+        ]);
+        assert!(cfg.unwrap().ip_kargs().is_err());
+        for mtu in ["575", "9001", "x"] {
+            assert!(install(&["--mtu", mtu]).is_err(), "{}", mtu);
+        }
+    }
+
+    #[test]
+    fn bond_guest() {
+        let znet = [
+            "--znet",
+            "qeth,0.0.bdf0,0.0.bdf1,0.0.bdf2,layer2=1",
+            "--znet",
+            "qeth,0.0.0600,0.0.0601,0.0.0602,layer2=1",
+        ];
+        let parse = |extra: &[&str]| install(&[&znet[..], extra].concat());
+        let cfg = parse(&["--bond", "bond0:encbdf0,enc600:mode=active-backup"]).unwrap();
+        assert_eq!(
+            cfg.ip_kargs().unwrap(),
+            ["172.23.237.227::172.23.0.1:255.255.0.0:coreos:bond0:none"]
+        );
+
+        let cfg = parse(&["--bond", "bond0:encbdf0,enc600", "--dhcp", "--vlan", "5"]).unwrap();
+        assert_eq!(cfg.ip_kargs().unwrap(), ["bond0.5:dhcp"]);
+        assert_eq!(cfg.vlan_karg().unwrap().unwrap(), "vlan=bond0.5:bond0");
+
+        let err = parse(&["--bond", "bond0:encbdf0,enc700"])
+            .unwrap()
+            .ip_kargs()
+            .unwrap_err();
+        assert!(err.to_string().contains("'enc700'"), "{}", err);
+        let err = parse(&[
+            "--bond",
+            "bond0:encbdf0,enc600",
+            "--ip",
+            "dhcp",
+            "--ip",
+            "dhcp",
+        ])
+        .unwrap()
+        .ip_kargs()
+        .unwrap_err();
+        assert!(
+            err.to_string().starts_with("2 --ip for 1 interfaces"),
+            "{}",
+            err
+        );
+        assert!(parse(&["--bond", "bond0:encbdf0"]).is_err());
+    }
+
+    #[test]
+    fn ipv6_guest() {
+        let ip = "[2001:db8::2]::10.0.0.1:64:guest:enc600:none";
+        let cfg = install(&["--nic", "enc600", "--ip", ip]).unwrap();
+        assert_eq!(
+            cfg.ip_kargs().unwrap_err().to_string(),
+            "10.0.0.1 is not an IPv6 address like the client address 2001:db8::2 of --ip, \
+pass --allow-mixed-ip-families if intended"
+        );
+        let mixed = ["--nic", "enc600", "--allow-mixed-ip-families", "--ip", ip];
+        let cfg = install(&mixed).unwrap();
+        assert_eq!(
+            cfg.ip_kargs().unwrap(),
+            ["[2001:db8::2]::10.0.0.1:64:guest:enc600:none"]
+        );
+    }
+
+    #[test]
+    fn https_builder() {
+        let build = build("https://mirror.example.com/builds/");
+        let live = Live::try_from(&build).unwrap();
+        assert_eq!(
+            live.kernel.as_str(),
+            "https://mirror.example.com/builds/fedora-coreos-37.20230314.dev.0-live-kernel-s390x"
+        );
+        assert_eq!(
+            live.rootfs.as_str(),
+            "https://mirror.example.com/builds/fedora-coreos-37.20230314.dev.0-live-rootfs.s390x.img"
+        );
+    }
+
+    #[test]
+    fn disk_ids() {
+        let parse = |disk: &[&str]| install(disk).map(|cfg| cfg.disk().unwrap().unwrap());
+        assert_eq!(
+            parse(&["--dasd", "5C6E"]).unwrap(),
+            DiskConfig::Dasd {
+                devno: "0.0.5c6e".into()
+            }
+        );
+        let err = parse(&["--dasd", "0.0.zzzz"]).unwrap_err();
+        assert_eq!(err.kind(), clap::ErrorKind::ValueValidation);
+        assert!(err
+            .to_string()
+            .contains("'0.0.zzzz' is not a device number, expected 0.0.XXXX"));
+        assert!(parse(&["--edev", "0.0.5c70x"]).is_err());
+        assert!(parse(&["--scsi", "0.0.1900,0x5005,0xlun"]).is_err());
+        let err = parse(&["--scsi", "0.0.1900,0x5005,0x4001,0x4002"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("lists 2 LUNs, expected a single one"));
+        assert!(parse(&["--target", "scsi", "--disk", "0.0.1900,0x5005,0xg"]).is_err());
+        assert!(parse(&["--target", "dasd", "--disk", "0.0.5c6e!"]).is_err());
+
+        let cfg = install(&["--target", "dasd", "--disk", "0.0.1900,0x5005,0x4001"]).unwrap();
+        assert_eq!(
+            format!("{:#}", cfg.disk().unwrap_err()),
+            "invalid --disk: '0.0.1900,0x5005,0x4001' is not a device number, expected 0.0.XXXX"
+        );
+    }
+
+    #[test]
+    fn disk_targets() {
+        let parse = |target: &[&str]| install(target).map(|cfg| cfg.disk());
+        let dasd = parse(&["--target", "dasd", "--disk", "0.0.5c6e"]).unwrap();
+        assert_eq!(
+            dasd.unwrap(),
+            parse(&["--dasd", "0.0.5c6e"]).unwrap().unwrap()
+        );
+        let mp = parse(&[
+            "--target",
+            "multipath",
+            "--disk",
+            "0.0.1900,0x5005,0x4001",
+            "--disk",
+            "0.0.1940,0x5015,0x4001",
+        ])
+        .unwrap()
+        .unwrap()
+        .unwrap();
+        assert_eq!(mp.install_dev(), "/dev/mapper/mpatha");
+        let named = parse(&[
+            "--mp",
+            "0.0.1900,0x5005,0x4001",
+            "--mp",
+            "0.0.1940,0x5015,0x4001",
+            "--mp-name",
+            "mpathc",
+        ])
+        .unwrap()
+        .unwrap()
+        .unwrap();
+        assert_eq!(named.install_dev(), "/dev/mapper/mpathc");
+        assert!(parse(&["--dasd", "0.0.5c6e", "--mp-name", "mpathc"])
+            .unwrap()
+            .is_err());
+        assert!(parse(&["--mp-name", "mpathc"]).unwrap().is_err());
+        assert!(parse(&["--target", "dasd"]).is_err());
+        assert!(parse(&["--target", "dasd", "--disk", "0.0.5c6e", "--scsi", "x"]).is_err());
+        assert!(
+            parse(&["--target", "edev", "--disk", "0.0.1", "--disk", "0.0.2"])
+                .unwrap()
+                .is_err()
+        );
+        assert!(parse(&[]).unwrap().unwrap().is_none());
+    }
+
+    #[test]
+    fn ip_follows_znet() {
+        let cfg = install(&[]).unwrap();
+        assert_eq!(cfg.ip_kargs().unwrap(), [cfg.ip[0].to_string()]);
+
+        let cfg = install(&["--znet", "qeth,0.0.0600,0.0.0601,0.0.0602,layer2=1"]).unwrap();
+        assert_eq!(
+            cfg.ip_kargs().unwrap(),
+            ["172.23.237.227::172.23.0.1:255.255.0.0:coreos:enc600:none"]
+        );
+
+        let iface = [
+            "--znet",
+            "qeth,0.0.0600,0.0.0601,0.0.0602",
+            "--iface",
+            "eth0",
+        ];
+        let cfg = install(&iface).unwrap();
+        assert_eq!(
+            cfg.ip_kargs().unwrap(),
+            ["172.23.237.227::172.23.0.1:255.255.0.0:coreos:eth0:none"]
+        );
+    }
+
+    #[test]
+    fn fcos_build_kinds() {
+        let mut build = build("http://builder/");
+        assert_eq!(
+            Live::try_from(&build).unwrap().kernel.as_str(),
+            "http://builder/fedora-coreos-37.20230314.dev.0-live-kernel-s390x"
+        );
+        build.build_kind = BuildKind::Stable;
+        build.date = Some("20230322".into());
+        assert_eq!(
+            Live::try_from(&build).unwrap().initrd.as_str(),
+            "http://builder/fedora-coreos-37.20230322.3.0-live-initramfs.s390x.img"
+        );
+    }
+
+    #[test]
+    fn env_defaults() {
+        let cmd = Cmd::command();
+        let install = cmd.find_subcommand("install").unwrap();
+        let env = |cmd: &clap::Command, id: &str| {
+            cmd.get_arguments()
+                .find(|arg| arg.get_id() == id)
+                .and_then(|arg| arg.get_env())
+                .map(|env| env.to_string_lossy().into_owned())
+        };
+        assert_eq!(env(install, "zvm").as_deref(), Some("ZVM_GUEST"));
+        assert_eq!(env(install, "znet").as_deref(), Some("ZVM_ZNET"));
+        assert_eq!(env(install, "ip").as_deref(), Some("ZVM_IP"));
+        assert_eq!(env(install, "dns").as_deref(), Some("ZVM_DNS"));
+        assert_eq!(env(install, "cache-dir").as_deref(), Some("ZVM_CACHE_DIR"));
+        let artifacts = install.find_subcommand("artifacts").unwrap();
+        assert_eq!(env(artifacts, "url").as_deref(), Some("ZVM_BUILDER_URL"));
+    }
+
+    #[test]
+    fn rootfs_url() {
+        assert!(parse_rootfs_url("http://cache.lab/rootfs.img").is_ok());
+        assert!(parse_rootfs_url("https://10.0.0.5:8080/rootfs.img").is_ok());
+        assert!(parse_rootfs_url("file:///srv/rootfs.img").is_err());
+        assert!(parse_rootfs_url("http://localhost/rootfs.img").is_err());
+        assert!(parse_rootfs_url("http://127.0.0.1/rootfs.img").is_err());
+        assert!(parse_rootfs_url("http://[::1]/rootfs.img").is_err());
+        assert!(parse_rootfs_url("rootfs.img").is_err());
+    }
+
+    #[test]
+    fn spool_class() {
+        assert_eq!(parse_spool_class("a"), Ok('A'));
+        assert_eq!(parse_spool_class("7"), Ok('7'));
+        assert!(parse_spool_class("").is_err());
+        assert!(parse_spool_class("AB").is_err());
+        assert!(parse_spool_class("*").is_err());
+    }
+
+    #[test]
+    fn spool_names() {
+        assert_eq!(
+            parse_spool_name("coreos.kernel"),
+            Ok("coreos.kernel".into())
+        );
+        assert_eq!(parse_spool_name("RHCOS.PRM"), Ok("RHCOS.PRM".into()));
+        for name in [
+            "coreos",
+            "coreos.",
+            ".parm",
+            "coreos.initramfs",
+            "longer123.parm",
+            "a.b.c",
+            "a b.c",
+        ] {
+            assert!(parse_spool_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn durations() {
+        for (s, secs) in [
+            ("600", 600),
+            ("90s", 90),
+            ("30m", 1800),
+            ("1h30m", 5400),
+            ("1d", 86400),
+        ] {
+            assert_eq!(parse_duration(s), Ok(Duration::from_secs(secs)), "{}", s);
+        }
+        for s in ["", "0", "0m", "m", "1h30", "30min", "-5s", "1.5h"] {
+            assert!(parse_duration(s).is_err(), "{}", s);
+        }
+        let cfg = install(&["--overall-timeout", "45"]).unwrap();
+        assert_eq!(cfg.deadline, Some(Duration::from_secs(45)));
+    }
+
+    #[test]
+    fn local_ignition() {
+        let parse = |ignition: &str, extra: &[&str]| install_with(ignition, extra, &[]).unwrap();
+        let cfg = parse("http://srv/ign.json", &[]);
+        assert_eq!(cfg.ignition_path(), None);
+        assert_eq!(cfg.serve_addr(), None);
+        let cfg = parse("file:///srv/ign.json", &[]);
+        assert_eq!(cfg.ignition_path(), Some(PathBuf::from("/srv/ign.json")));
+        let cfg = parse("ign.json", &["--ignition-serve", ":8080"]);
+        assert_eq!(cfg.ignition_path(), Some(PathBuf::from("ign.json")));
+        assert_eq!(cfg.serve_addr().unwrap().port, 8080);
+
+        let file = std::env::temp_dir().join(format!("zvmhelper-ign-{}.json", std::process::id()));
+        let cfg = parse(file.to_str().unwrap(), &[]);
+        assert_eq!(cfg.serve_addr(), Some(ServeAddr { ip: None, port: 0 }));
+        assert_eq!(
+            cfg.validate().unwrap_err().to_string(),
+            format!("Ignition config '{}' does not exist", file.display())
+        );
+        std::fs::write(&file, "{}").unwrap();
+        cfg.validate().unwrap();
+        std::fs::remove_file(&file).unwrap();
+        assert!(parse("http://srv/ign.json", &["--ignition-serve", ":8080"])
+            .validate()
+            .is_err());
+    }
 }
@@ -0,0 +1,951 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{anyhow, bail, ensure, Context, Error, Result};
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+
+/// Static guest network configuration, the structured form of
+/// `ip=<client>:<peer>:<gw>:<netmask>:<hostname>:<nic>:<autoconf>[:...]`,
+/// IPv6 addresses are bracketed and their netmask is a prefix length
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkConfig {
+    pub ip: IpAddr,
+    pub peer: Option<IpAddr>,
+    pub gw: Option<IpAddr>,
+    /// Netmask as prefix length
+    pub mask: Option<u32>,
+    pub hostname: Option<String>,
+    pub nic: Option<String>,
+    /// `dhcp` instead of `none` autoconfiguration
+    pub dhcp: bool,
+    /// Trailing MTU/MAC or DNS fields, kept verbatim
+    pub extra: Vec<String>,
+}
+
+/// dracut autoconfiguration methods
+const AUTOCONF: [&str; 11] = [
+    "off",
+    "none",
+    "on",
+    "any",
+    "dhcp",
+    "dhcp6",
+    "auto6",
+    "either6",
+    "link6",
+    "ibft",
+    "single-dhcp",
+];
+
+/// Guest network configuration, the structured form of the dracut `ip=`
+/// karg
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IpConfig {
+    /// `ip=<autoconf>`, e.g. `dhcp`
+    Auto(String),
+    /// `ip=<nic>:<autoconf>[:<mtu>[:<macaddr>]]`
+    Interface {
+        nic: String,
+        autoconf: String,
+        extra: Vec<String>,
+    },
+    /// `ip=<client>:[<peer>]:<gw>:<netmask>:<hostname>:<nic>:<autoconf>[:...]`
+    Static(NetworkConfig),
+}
+
+impl IpConfig {
+    /// The static configuration, if that is the form
+    pub fn static_config(&self) -> Option<&NetworkConfig> {
+        match self {
+            Self::Static(net) => Some(net),
+            _ => None,
+        }
+    }
+}
+
+fn autoconf(field: &str) -> Result<String> {
+    ensure!(
+        AUTOCONF.contains(&field),
+        "unsupported autoconfiguration '{}', expected one of {}",
+        field,
+        AUTOCONF.join(", ")
+    );
+    Ok(field.to_string())
+}
+
+/// Checks the `[:<mtu>[:<macaddr>]]` or `[:<dns1>[:<dns2>]]` trailing
+/// fields, split on every colon
+fn check_extra(extra: &[&str]) -> Result<()> {
+    let Some(first) = extra.first() else {
+        return Ok(());
+    };
+    if address(first).is_some() {
+        ensure!(
+            extra.len() <= 2 && extra.iter().all(|f| f.is_empty() || address(f).is_some()),
+            "invalid nameserver fields '{}', expected <dns1>[:<dns2>]",
+            extra.join(":")
+        );
+        return Ok(());
+    }
+    ensure!(
+        first.is_empty() || first.parse::<u32>().is_ok(),
+        "invalid MTU '{}'",
+        first
+    );
+    let mac = &extra[1..];
+    ensure!(
+        mac.is_empty()
+            || (mac.len() == 6
+                && mac
+                    .iter()
+                    .all(|b| b.len() == 2 && b.chars().all(|c| c.is_ascii_hexdigit()))),
+        "invalid MAC address '{}'",
+        mac.join(":")
+    );
+    Ok(())
+}
+
+impl FromStr for IpConfig {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let fields = split_fields(s);
+        if fields.len() == 1 {
+            return Ok(Self::Auto(autoconf(s)?));
+        }
+        // a mistyped address is no interface name
+        if fields[0].starts_with('[') || fields[0].chars().all(|c| c.is_ascii_digit() || c == '.') {
+            return Ok(Self::Static(s.parse()?));
+        }
+        ensure!(
+            !fields[0].is_empty()
+                && fields[0]
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c)),
+            "'{}' is neither a client address nor an interface name",
+            fields[0]
+        );
+        check_extra(&fields[2..])?;
+        Ok(Self::Interface {
+            nic: fields[0].to_string(),
+            autoconf: autoconf(fields[1])?,
+            extra: fields[2..].iter().map(<_>::to_string).collect(),
+        })
+    }
+}
+
+impl fmt::Display for IpConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auto(autoconf) => write!(f, "{}", autoconf),
+            Self::Interface {
+                nic,
+                autoconf,
+                extra,
+            } => {
+                write!(f, "{}:{}", nic, autoconf)?;
+                for extra in extra {
+                    write!(f, ":{}", extra)?;
+                }
+                Ok(())
+            }
+            Self::Static(net) => write!(f, "{}", net),
+        }
+    }
+}
+
+/// `[<addr>]:<port>` the Ignition config is served on, the address is
+/// derived from the route to the guest when omitted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServeAddr {
+    pub ip: Option<IpAddr>,
+    pub port: u16,
+}
+
+impl FromStr for ServeAddr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(port) = s.strip_prefix(':') {
+            let port = port
+                .parse()
+                .map_err(|_| anyhow!("invalid port '{}'", port))?;
+            return Ok(Self { ip: None, port });
+        }
+        let addr: SocketAddr = s
+            .parse()
+            .map_err(|_| anyhow!("'{}' is not <addr>:<port> or :<port>", s))?;
+        Ok(Self {
+            ip: Some(addr.ip()),
+            port: addr.port(),
+        })
+    }
+}
+
+/// Virtual reader, punch and printer of the helper's own guest, as ccw
+/// bus ids like `0.0.000c`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmurDevices {
+    pub rdr: String,
+    pub pun: String,
+    pub prt: String,
+}
+
+/// `0.0.<devno>` bus id of a device number given as `c`, `000c` or
+/// `0.0.000c`
+pub fn bus_id(devno: &str) -> Result<String> {
+    let hex = devno.strip_prefix("0.0.").unwrap_or(devno);
+    ensure!(
+        !hex.is_empty() && hex.len() <= 4 && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        "'{}' is not a device number, expected 0.0.XXXX",
+        devno
+    );
+    Ok(format!("0.0.{:0>4}", hex.to_ascii_lowercase()))
+}
+
+impl VmurDevices {
+    pub fn all(&self) -> [&str; 3] {
+        [&self.rdr, &self.pun, &self.prt]
+    }
+}
+
+impl FromStr for VmurDevices {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let ids = s.split(',').map(bus_id).collect::<Result<Vec<_>>>()?;
+        let [rdr, pun, prt] = <[String; 3]>::try_from(ids)
+            .map_err(|_| anyhow!("expected <rdr>,<pun>,<prt> device numbers, got '{}'", s))?;
+        ensure!(
+            rdr != pun && pun != prt && rdr != prt,
+            "reader, punch and printer must be different devices, got '{}'",
+            s
+        );
+        Ok(Self { rdr, pun, prt })
+    }
+}
+
+/// Bonded interface, the structured form of the dracut
+/// `bond=<name>:<slave>,<slave>[,...][:<options>[:<mtu>]]` karg
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BondConfig {
+    pub name: String,
+    pub slaves: Vec<String>,
+    /// Comma separated bonding options, e.g. `mode=active-backup`
+    pub options: Option<String>,
+    pub mtu: Option<u32>,
+}
+
+fn interface_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 15
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+}
+
+impl FromStr for BondConfig {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let fields: Vec<&str> = s.split(':').collect();
+        ensure!(
+            (2..=4).contains(&fields.len()),
+            "'{}' is not a bond, expected <name>:<slave>,<slave>[:<options>[:<mtu>]]",
+            s
+        );
+        ensure!(
+            interface_name(fields[0]),
+            "invalid bond name '{}'",
+            fields[0]
+        );
+        let slaves: Vec<String> = fields[1].split(',').map(<_>::to_string).collect();
+        ensure!(
+            slaves.len() > 1,
+            "bond '{}' needs at least two slaves, got '{}'",
+            fields[0],
+            fields[1]
+        );
+        for (n, slave) in slaves.iter().enumerate() {
+            ensure!(interface_name(slave), "invalid bond slave '{}'", slave);
+            ensure!(
+                !slaves[..n].contains(slave) && slave != fields[0],
+                "bond slave '{}' is given twice",
+                slave
+            );
+        }
+        let options = match fields.get(2) {
+            None | Some(&"") => None,
+            Some(options) => {
+                ensure!(
+                    options.split(',').all(|o| o
+                        .split_once('=')
+                        .is_some_and(|(k, v)| !k.is_empty() && !v.is_empty())),
+                    "invalid bond options '{}', expected <option>=<value>[,...]",
+                    options
+                );
+                Some(options.to_string())
+            }
+        };
+        let mtu = match fields.get(3) {
+            None => None,
+            Some(mtu) => Some(mtu.parse().map_err(|_| anyhow!("invalid MTU '{}'", mtu))?),
+        };
+        Ok(Self {
+            name: fields[0].to_string(),
+            slaves,
+            options,
+            mtu,
+        })
+    }
+}
+
+impl fmt::Display for BondConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.name, self.slaves.join(","))?;
+        match (&self.options, self.mtu) {
+            (options, Some(mtu)) => {
+                write!(f, ":{}:{}", options.as_deref().unwrap_or_default(), mtu)
+            }
+            (Some(options), None) => write!(f, ":{}", options),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Predictable name of the interface of a `rd.znet` device, systemd names
+/// ccw devices `enc` and the bus id of the first subchannel without its
+/// leading zeros and dots, e.g. `encbdf0` for `qeth,0.0.bdf0,...`
+pub fn znet_iface(znet: &str) -> Option<String> {
+    let bus_id = znet.split(',').nth(1)?;
+    let devno = bus_id.trim_start_matches(['0', '.']);
+    if devno.is_empty() {
+        return None;
+    }
+    Some(format!("enc{}", devno.to_ascii_lowercase()))
+}
+
+/// Fields of an `ip=` value, split on colons outside of `[...]`
+fn split_fields(s: &str) -> Vec<&str> {
+    let mut fields = Vec::new();
+    let (mut start, mut bracket) = (0, false);
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' => bracket = true,
+            ']' => bracket = false,
+            ':' if !bracket => {
+                fields.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    fields.push(&s[start..]);
+    fields
+}
+
+/// `a.b.c.d` or `[v6]` address of an `ip=` field
+fn address(field: &str) -> Option<IpAddr> {
+    match field.strip_prefix('[').and_then(|f| f.strip_suffix(']')) {
+        Some(v6) => v6.parse::<Ipv6Addr>().ok().map(IpAddr::V6),
+        None => field.parse::<Ipv4Addr>().ok().map(IpAddr::V4),
+    }
+}
+
+fn optional_address(field: &str, what: &str) -> Result<Option<IpAddr>> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+    address(field)
+        .map(Some)
+        .ok_or_else(|| anyhow!("invalid {} '{}'", what, field))
+}
+
+/// `ip=` form of `addr`, bracketed for IPv6
+pub fn ip_field(addr: &IpAddr) -> String {
+    match addr {
+        IpAddr::V4(v4) => v4.to_string(),
+        IpAddr::V6(v6) => format!("[{}]", v6),
+    }
+}
+
+fn optional<T: FromStr>(field: &str, what: &str) -> Result<Option<T>> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+    field
+        .parse()
+        .map(Some)
+        .map_err(|_| anyhow!("invalid {} '{}'", what, field))
+}
+
+fn parse_mask(mask: &str, v6: bool) -> Result<u32> {
+    let max = if v6 { 128 } else { 32 };
+    match mask.parse::<u32>() {
+        Ok(prefix) if prefix <= max => Ok(prefix),
+        Ok(prefix) => bail!("invalid prefix length '{}'", prefix),
+        Err(_) if v6 => bail!("invalid IPv6 prefix length '{}'", mask),
+        Err(_) => {
+            let bits = u32::from(
+                mask.parse::<Ipv4Addr>()
+                    .map_err(|_| anyhow!("invalid netmask '{}'", mask))?,
+            );
+            if bits.leading_ones() + bits.trailing_zeros() != 32 {
+                bail!("netmask '{}' is not contiguous", mask);
+            }
+            Ok(bits.leading_ones())
+        }
+    }
+}
+
+fn mask_bits(prefix: u32) -> u32 {
+    u32::MAX.checked_shl(32 - prefix).unwrap_or(0)
+}
+
+/// Network of `ip` with a `prefix` long netmask
+fn network(ip: IpAddr, prefix: u32) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => Ipv4Addr::from(u32::from(v4) & mask_bits(prefix)).into(),
+        IpAddr::V6(v6) => {
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            Ipv6Addr::from(u128::from(v6) & mask).into()
+        }
+    }
+}
+
+impl FromStr for NetworkConfig {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let fields = split_fields(s);
+        if fields.len() < 4 {
+            bail!("'{}' is not a static ip= configuration", s);
+        }
+        let field = |n: usize| fields.get(n).copied().unwrap_or_default();
+        let dhcp = match field(6) {
+            "" | "none" | "off" => false,
+            "dhcp" | "on" | "any" => true,
+            other => bail!("unsupported autoconfiguration '{}'", other),
+        };
+        let ip =
+            address(field(0)).ok_or_else(|| anyhow!("invalid client address '{}'", field(0)))?;
+        let cfg = Self {
+            ip,
+            peer: optional_address(field(1), "peer address")?,
+            gw: optional_address(field(2), "gateway address")?,
+            mask: match field(3) {
+                "" => None,
+                mask => Some(parse_mask(mask, ip.is_ipv6())?),
+            },
+            hostname: optional(field(4), "hostname")?,
+            nic: optional(field(5), "interface")?,
+            dhcp,
+            extra: fields.iter().skip(7).map(<_>::to_string).collect(),
+        };
+        check_extra(&fields[fields.len().min(7)..])?;
+        if let (Some(gw), Some(prefix)) = (cfg.gw, cfg.mask) {
+            if gw.is_ipv6() == ip.is_ipv6() && network(ip, prefix) != network(gw, prefix) {
+                bail!(
+                    "gateway {} is outside of the {}/{} network",
+                    gw,
+                    network(ip, prefix),
+                    prefix
+                );
+            }
+        }
+        Ok(cfg)
+    }
+}
+
+impl NetworkConfig {
+    /// Peer or gateway addresses of another family than the client one
+    pub fn mixed_families(&self) -> Vec<IpAddr> {
+        [self.peer, self.gw]
+            .iter()
+            .flatten()
+            .filter(|addr| addr.is_ipv6() != self.ip.is_ipv6())
+            .copied()
+            .collect()
+    }
+}
+
+impl fmt::Display for NetworkConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let opt = |v: Option<String>| v.unwrap_or_default();
+        let mask = |m: u32| match self.ip {
+            IpAddr::V4(_) => Ipv4Addr::from(mask_bits(m)).to_string(),
+            IpAddr::V6(_) => m.to_string(),
+        };
+        write!(
+            f,
+            "{}:{}:{}:{}:{}:{}:{}",
+            ip_field(&self.ip),
+            opt(self.peer.as_ref().map(ip_field)),
+            opt(self.gw.as_ref().map(ip_field)),
+            opt(self.mask.map(mask)),
+            opt(self.hostname.clone()),
+            opt(self.nic.clone()),
+            if self.dhcp { "dhcp" } else { "none" }
+        )?;
+        for extra in &self.extra {
+            write!(f, ":{}", extra)?;
+        }
+        Ok(())
+    }
+}
+
+/// One zFCP path to a set of LUNs, `<devno>,<wwpn>,<lun>[,<lun>...]`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ZfcpPath {
+    pub devno: String,
+    pub wwpn: String,
+    pub luns: Vec<String>,
+}
+
+impl ZfcpPath {
+    /// `rd.zfcp=` values, one per LUN
+    pub fn rd_zfcp(&self) -> impl Iterator<Item = String> + '_ {
+        self.luns
+            .iter()
+            .map(move |lun| format!("{},{},{}", self.devno, self.wwpn, lun))
+    }
+}
+
+impl FromStr for ZfcpPath {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let fields: Vec<&str> = s.split(',').collect();
+        ensure!(
+            fields.len() >= 3 && fields.iter().all(|f| !f.is_empty()),
+            "'{}' is not a zFCP path, expected <devno>,<wwpn>,<lun>[,<lun>...]",
+            s
+        );
+        let context = || format!("invalid zFCP path '{}'", s);
+        Ok(Self {
+            devno: bus_id(fields[0]).with_context(context)?,
+            wwpn: fcp_number(fields[1], "WWPN").with_context(context)?,
+            luns: fields[2..]
+                .iter()
+                .map(|lun| fcp_number(lun, "LUN"))
+                .collect::<Result<_>>()
+                .with_context(context)?,
+        })
+    }
+}
+
+/// Lowercase WWPN or LUN, `0x` and up to 16 hex digits
+fn fcp_number(s: &str, what: &str) -> Result<String> {
+    let hex = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or_default();
+    ensure!(
+        !hex.is_empty() && hex.len() <= 16 && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        "'{}' is not a {}, expected 0x and up to 16 hex digits",
+        s,
+        what
+    );
+    Ok(format!("0x{}", hex.to_ascii_lowercase()))
+}
+
+impl fmt::Display for ZfcpPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{}", self.devno, self.wwpn, self.luns.join(","))
+    }
+}
+
+/// zFCP paths grouped into multipath devices, one per LUN
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MultipathDisks {
+    pub paths: Vec<ZfcpPath>,
+    /// Device-mapper name of the map, when not the first friendly name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl MultipathDisks {
+    /// Checks that there are at least two distinct paths and that all of
+    /// them lead to the same LUNs
+    pub fn new(paths: Vec<ZfcpPath>) -> Result<Self> {
+        ensure!(
+            paths.len() > 1,
+            "multipath needs at least two zFCP paths, got {}",
+            paths.len()
+        );
+        let first = &paths[0];
+        for (n, path) in paths.iter().enumerate() {
+            let mut luns = path.luns.clone();
+            luns.sort();
+            luns.dedup();
+            ensure!(
+                luns.len() == path.luns.len(),
+                "zFCP path '{}' lists a LUN twice",
+                path
+            );
+            let mut expected = first.luns.clone();
+            expected.sort();
+            ensure!(
+                luns == expected,
+                "zFCP path '{}' does not lead to the same LUNs as '{}'",
+                path,
+                first
+            );
+            ensure!(
+                !paths[..n]
+                    .iter()
+                    .any(|p| p.devno == path.devno && p.wwpn == path.wwpn),
+                "zFCP path {},{} is given twice",
+                path.devno,
+                path.wwpn
+            );
+        }
+        Ok(Self { paths, name: None })
+    }
+
+    /// Installs to the map named `name` instead, a multipath alias or a
+    /// friendly name like `mpathb` when other maps are discovered first
+    pub fn named(mut self, name: &str) -> Result<Self> {
+        ensure!(
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.+#@".contains(c)),
+            "invalid multipath map name '{}'",
+            name
+        );
+        self.name = Some(name.to_string());
+        Ok(self)
+    }
+
+    /// `rd.zfcp=` values of all paths
+    pub fn rd_zfcp(&self) -> Vec<String> {
+        self.paths.iter().flat_map(ZfcpPath::rd_zfcp).collect()
+    }
+
+    /// Multipath maps get friendly names in the order the LUNs are
+    /// discovered, the install target is the first LUN so `mpatha` unless
+    /// named otherwise. The WWID behind the LUN is only known once the
+    /// device is online, so the name can't be derived from the paths.
+    pub fn install_target(&self) -> String {
+        format!("/dev/mapper/{}", self.name.as_deref().unwrap_or("mpatha"))
+    }
+}
+
+/// Disk the installer writes to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum DiskConfig {
+    /// ECKD DASD
+    Dasd {
+        devno: String,
+    },
+    /// FBA DASD
+    Edev {
+        devno: String,
+    },
+    /// zFCP SCSI LUN, `<devno>,<wwpn>,<lun>`
+    Scsi {
+        path: String,
+    },
+    Multipath(MultipathDisks),
+}
+
+impl DiskConfig {
+    /// `rd.dasd=`/`rd.zfcp=` kargs bringing the disk online
+    pub fn rd_kargs(&self) -> Vec<String> {
+        match self {
+            Self::Dasd { devno } | Self::Edev { devno } => vec![format!("rd.dasd={}", devno)],
+            Self::Scsi { path } => vec![format!("rd.zfcp={}", path)],
+            Self::Multipath(mp) => std::iter::once("rd.multipath=default".to_string())
+                .chain(mp.rd_zfcp().iter().map(|p| format!("rd.zfcp={}", p)))
+                .collect(),
+        }
+    }
+
+    /// `coreos.inst.install_dev=` of the disk
+    pub fn install_dev(&self) -> String {
+        match self {
+            Self::Dasd { devno } | Self::Edev { devno } => {
+                format!("/dev/disk/by-path/ccw-{}", devno)
+            }
+            Self::Scsi { .. } => "sda".to_string(),
+            Self::Multipath(mp) => mp.install_target(),
+        }
+    }
+}
+
+impl fmt::Display for DiskConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dasd { devno } => write!(f, "ECKD-DASD {}", devno),
+            Self::Edev { devno } => write!(f, "EDEV-DASD(FBA) {}", devno),
+            Self::Scsi { path } => write!(f, "zFCP {}", path),
+            Self::Multipath(mp) => {
+                let paths: Vec<String> = mp.paths.iter().map(<_>::to_string).collect();
+                write!(f, "Multipath {}", paths.join(" "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn network_round_trip() {
+        for ip in [
+            "172.23.237.227::172.23.0.1:255.255.0.0:coreos:encbdf0:none",
+            "10.0.0.2:10.0.0.3:10.0.0.1:255.255.255.0::enc600:dhcp",
+            "10.0.0.2::10.0.0.1:255.255.255.0:guest:enc600:none:9000",
+        ] {
+            assert_eq!(ip.parse::<NetworkConfig>().unwrap().to_string(), ip);
+        }
+
+        let net: NetworkConfig = "172.23.237.227::172.23.0.1:16:coreos:encbdf0:none"
+            .parse()
+            .unwrap();
+        assert_eq!(net.mask, Some(16));
+        assert_eq!(net.hostname.as_deref(), Some("coreos"));
+        assert_eq!(net.nic.as_deref(), Some("encbdf0"));
+        assert_eq!(
+            net.to_string(),
+            "172.23.237.227::172.23.0.1:255.255.0.0:coreos:encbdf0:none"
+        );
+
+        let net: NetworkConfig = "10.0.0.2::10.0.0.1:24".parse().unwrap();
+        assert_eq!(net.to_string(), "10.0.0.2::10.0.0.1:255.255.255.0:::none");
+    }
+
+    #[test]
+    fn ip_forms() {
+        for ip in [
+            "dhcp",
+            "enc600:dhcp",
+            "enc600:dhcp:9000",
+            "enc600:dhcp:9000:02:00:00:00:00:01",
+            "172.23.237.227::172.23.0.1:255.255.0.0:coreos:encbdf0:none",
+            "10.0.0.2::10.0.0.1:255.255.255.0:guest:enc600:none:10.0.0.53",
+        ] {
+            assert_eq!(ip.parse::<IpConfig>().unwrap().to_string(), ip);
+        }
+        let ip: IpConfig = "10.0.0.2::10.0.0.1:24".parse().unwrap();
+        assert_eq!(ip.static_config().unwrap().mask, Some(24));
+
+        let err = |ip: &str| ip.parse::<IpConfig>().unwrap_err().to_string();
+        assert!(err("dchp").starts_with("unsupported autoconfiguration 'dchp'"));
+        assert!(err("enc600:static").starts_with("unsupported autoconfiguration 'static'"));
+        assert_eq!(err("enc600:dhcp:jumbo"), "invalid MTU 'jumbo'");
+        assert_eq!(err("enc600:dhcp:9000:02:00"), "invalid MAC address '02:00'");
+        assert_eq!(
+            err("172.23.237.227:172.23.0.1:255.255.0.0:coreos:encbdf0:none"),
+            "invalid netmask 'coreos'"
+        );
+        assert_eq!(
+            err("172.23.237.227::172.23.0.1:255.255.0.0:coreos:encbdf0:none:10.0.0.53:x"),
+            "invalid nameserver fields '10.0.0.53:x', expected <dns1>[:<dns2>]"
+        );
+        assert_eq!(
+            err("enc 600:dhcp"),
+            "'enc 600' is neither a client address nor an interface name"
+        );
+        assert_eq!(
+            err("172.23.237.2270::172.23.0.1:255.255.0.0:coreos:encbdf0:none"),
+            "invalid client address '172.23.237.2270'"
+        );
+    }
+
+    #[test]
+    fn ipv6_network() {
+        let ip = "[2001:db8::2]::[2001:db8::1]:64:guest:enc600:none:[2001:db8::53]";
+        let net: NetworkConfig = ip.parse().unwrap();
+        assert_eq!(net.ip, "2001:db8::2".parse::<IpAddr>().unwrap());
+        assert_eq!(net.mask, Some(64));
+        assert_eq!(net.to_string(), ip);
+        assert!(net.mixed_families().is_empty());
+        assert_eq!(ip.parse::<IpConfig>().unwrap().to_string(), ip);
+
+        let err = "[2001:db8::2]::[2001:db9::1]:64".parse::<NetworkConfig>();
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "gateway 2001:db9::1 is outside of the 2001:db8::/64 network"
+        );
+        let err = "[2001:db8::2]::[2001:db8::1]:ffff::".parse::<NetworkConfig>();
+        assert!(err.is_err());
+
+        let net: NetworkConfig = "[2001:db8::2]::10.0.0.1:64".parse().unwrap();
+        assert_eq!(
+            net.mixed_families(),
+            ["10.0.0.1".parse::<IpAddr>().unwrap()]
+        );
+    }
+
+    #[test]
+    fn serve_addrs() {
+        let addr: ServeAddr = "10.0.0.1:8080".parse().unwrap();
+        assert_eq!(addr.ip, Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(addr.port, 8080);
+        let addr: ServeAddr = ":8080".parse().unwrap();
+        assert_eq!((addr.ip, addr.port), (None, 8080));
+        assert!("10.0.0.1".parse::<ServeAddr>().is_err());
+        assert!(":http".parse::<ServeAddr>().is_err());
+    }
+
+    #[test]
+    fn vmur_devices() {
+        let devices: VmurDevices = "c,d,e".parse().unwrap();
+        assert_eq!(devices.all(), ["0.0.000c", "0.0.000d", "0.0.000e"]);
+        let devices: VmurDevices = "0.0.0A0C,00d,e".parse().unwrap();
+        assert_eq!(devices.all(), ["0.0.0a0c", "0.0.000d", "0.0.000e"]);
+        assert!("c,d".parse::<VmurDevices>().is_err());
+        assert!("c,d,e,f".parse::<VmurDevices>().is_err());
+        assert!("c,c,e".parse::<VmurDevices>().is_err());
+        assert!("c,d,0x0e".parse::<VmurDevices>().is_err());
+        assert!("c,d,10000".parse::<VmurDevices>().is_err());
+    }
+
+    #[test]
+    fn zfcp_paths() {
+        let path: ZfcpPath = "0.0.1900,0x500507630400D1E3,0x4001401100000000"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            path.to_string(),
+            "0.0.1900,0x500507630400d1e3,0x4001401100000000"
+        );
+        let path: ZfcpPath = "1900,0x5005,0x4001,0x4002".parse().unwrap();
+        assert_eq!(path.to_string(), "0.0.1900,0x5005,0x4001,0x4002");
+
+        let err = "0.0.zzzz,0x5005,0x4001".parse::<ZfcpPath>().unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "invalid zFCP path '0.0.zzzz,0x5005,0x4001': '0.0.zzzz' is not a device number, expected 0.0.XXXX"
+        );
+        let err = "0.0.1900,5005,0x4001".parse::<ZfcpPath>().unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "invalid zFCP path '0.0.1900,5005,0x4001': '5005' is not a WWPN, expected 0x and up to 16 hex digits"
+        );
+        let err = "0.0.1900,0x5005,0x40014011000000001"
+            .parse::<ZfcpPath>()
+            .unwrap_err();
+        assert!(format!("{:#}", err).ends_with("is not a LUN, expected 0x and up to 16 hex digits"));
+        assert!("0.0.1900:0x5005:0x4001".parse::<ZfcpPath>().is_err());
+    }
+
+    #[test]
+    fn bonds() {
+        for bond in [
+            "bond0:encbdf0,enc600",
+            "bond0:encbdf0,enc600:mode=active-backup,miimon=100",
+            "bond0:encbdf0,enc600::9000",
+            "bond0:encbdf0,enc600:mode=802.3ad:9000",
+        ] {
+            assert_eq!(bond.parse::<BondConfig>().unwrap().to_string(), bond);
+        }
+        let bond: BondConfig = "bond0:encbdf0,enc600:".parse().unwrap();
+        assert_eq!(bond.to_string(), "bond0:encbdf0,enc600");
+        assert_eq!(bond.slaves, ["encbdf0", "enc600"]);
+
+        let err = |s: &str| s.parse::<BondConfig>().unwrap_err().to_string();
+        assert_eq!(
+            err("bond0:encbdf0"),
+            "bond 'bond0' needs at least two slaves, got 'encbdf0'"
+        );
+        assert_eq!(
+            err("bond0:encbdf0,encbdf0"),
+            "bond slave 'encbdf0' is given twice"
+        );
+        assert_eq!(
+            err("bond0:encbdf0,enc600:mode"),
+            "invalid bond options 'mode', expected <option>=<value>[,...]"
+        );
+        assert_eq!(err("bond0:encbdf0,enc600::jumbo"), "invalid MTU 'jumbo'");
+        assert!("bond 0:a,b".parse::<BondConfig>().is_err());
+        assert!("bond0".parse::<BondConfig>().is_err());
+    }
+
+    #[test]
+    fn znet_interfaces() {
+        let iface = znet_iface;
+        assert_eq!(
+            iface("qeth,0.0.bdf0,0.0.bdf1,0.0.bdf2,layer2=1").as_deref(),
+            Some("encbdf0")
+        );
+        assert_eq!(
+            iface("qeth,0.0.0600,0.0.0601,0.0.0602").as_deref(),
+            Some("enc600")
+        );
+        assert_eq!(
+            iface("qeth,0.1.0A00,0.1.0A01,0.1.0A02").as_deref(),
+            Some("enc1.0a00")
+        );
+        assert_eq!(iface("qeth"), None);
+    }
+
+    #[test]
+    fn multipath_two_paths_two_luns() {
+        let paths = [
+            "0.0.1900,0x500507630400d1e3,0x4001401100000000,0x4001401200000000",
+            "0.0.1940,0x500507630410d1e3,0x4001401200000000,0x4001401100000000",
+        ];
+        let mp = MultipathDisks::new(paths.iter().map(|p| p.parse().unwrap()).collect()).unwrap();
+        assert_eq!(
+            mp.rd_zfcp(),
+            [
+                "0.0.1900,0x500507630400d1e3,0x4001401100000000",
+                "0.0.1900,0x500507630400d1e3,0x4001401200000000",
+                "0.0.1940,0x500507630410d1e3,0x4001401200000000",
+                "0.0.1940,0x500507630410d1e3,0x4001401100000000",
+            ]
+        );
+        assert_eq!(mp.install_target(), "/dev/mapper/mpatha");
+    }
+
+    #[test]
+    fn multipath_validation() {
+        let mp = |paths: &[&str]| {
+            MultipathDisks::new(paths.iter().map(|p| p.parse().unwrap()).collect())
+        };
+        assert!(mp(&["0.0.1900,0x5005,0x4001"]).is_err());
+        assert!(mp(&["0.0.1900,0x5005,0x4001", "0.0.1940,0x5006,0x4002"]).is_err());
+        assert!(mp(&["0.0.1900,0x5005,0x4001", "0.0.1900,0x5005,0x4001"]).is_err());
+        assert!(mp(&[
+            "0.0.1900,0x5005,0x4001,0x4001",
+            "0.0.1940,0x5006,0x4001,0x4001"
+        ])
+        .is_err());
+        assert!(mp(&["0.0.1900,0x5005,0x4001", "0.0.1940,0x5006,0x4001"]).is_ok());
+        assert!("0.0.1900,0x5005".parse::<ZfcpPath>().is_err());
+    }
+
+    #[test]
+    fn multipath_name() {
+        let mp = MultipathDisks::new(vec![
+            "0.0.1900,0x5005,0x4001".parse().unwrap(),
+            "0.0.1940,0x5006,0x4001".parse().unwrap(),
+        ])
+        .unwrap();
+        assert_eq!(
+            mp.clone().named("rootdisk").unwrap().install_target(),
+            "/dev/mapper/rootdisk"
+        );
+        assert!(mp.clone().named("").is_err());
+        assert!(mp.clone().named("../sda").is_err());
+        assert!(mp.named("mpath b").is_err());
+    }
+}
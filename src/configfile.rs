@@ -0,0 +1,406 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `--config` files: the long option names of `install`/`verify` as keys,
+//! and a table or map named after the images subcommand for its options.
+//! Values are booleans, numbers, strings or lists of them:
+//!
+//! ```toml
+//! zvm = "guest1"
+//! dns = ["10.0.0.1", "10.0.0.2"]
+//! check-network = true
+//!
+//! [artifacts]
+//! build = "37.20230322.3.0"
+//! ```
+//!
+//! ```yaml
+//! zvm: guest1
+//! dns:
+//!   - 10.0.0.1
+//! artifacts:
+//!   build: 37.20230322.3.0
+//! ```
+//!
+//! The file is expanded into command line arguments placed before the
+//! given ones, so that flags override it.
+
+use crate::cmdline::Cmd;
+use anyhow::{bail, Context, Result};
+use clap::{ArgAction, CommandFactory};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::Path;
+
+/// Value of an option in the file, or a table of the images options
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum Node {
+    Value(Value),
+    Table(BTreeMap<String, Value>),
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum Value {
+    Bool(bool),
+    Scalar(Scalar),
+    List(Vec<Scalar>),
+}
+
+/// A string, or a number kept as written for clap to parse
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum Scalar {
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl fmt::Display for Scalar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(n) => write!(f, "{}", n),
+            Self::Float(n) => write!(f, "{}", n),
+            Self::String(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Entry {
+    section: Option<String>,
+    key: String,
+    value: Value,
+}
+
+/// Entries of a TOML or YAML `text`, tables being the images sections
+fn entries(text: &str, yaml: bool) -> Result<Vec<Entry>> {
+    let nodes: BTreeMap<String, Node> = match yaml {
+        // an empty YAML document is null, not an empty map
+        true if text
+            .lines()
+            .all(|l| l.trim().is_empty() || l.trim_start().starts_with('#')) =>
+        {
+            BTreeMap::new()
+        }
+        true => serde_yaml::from_str(text)?,
+        false => toml::from_str(text)?,
+    };
+    let mut entries = Vec::new();
+    for (key, node) in nodes {
+        match node {
+            Node::Value(value) => entries.push(Entry {
+                section: None,
+                key,
+                value,
+            }),
+            Node::Table(table) => entries.extend(table.into_iter().map(|(k, value)| Entry {
+                section: Some(key.clone()),
+                key: k,
+                value,
+            })),
+        }
+    }
+    Ok(entries)
+}
+
+/// Whether `arg` is given in `cli`
+fn on_command_line(arg: &clap::Arg, cli: &[OsString]) -> bool {
+    let mut longs = arg.get_all_aliases().unwrap_or_default();
+    longs.extend(arg.get_long());
+    cli.iter().filter_map(|a| a.to_str()).any(|a| {
+        longs.iter().any(|long| {
+            a.strip_prefix("--")
+                .map(|a| a.split('=').next() == Some(long))
+                .unwrap_or(false)
+        }) || arg.get_short().is_some_and(|short| {
+            a.len() > 1 && a.starts_with('-') && !a.starts_with("--") && a[1..].starts_with(short)
+        })
+    })
+}
+
+/// Arguments for `entry` of the options of `cmd`
+fn entry_args(
+    path: &Path,
+    cmd: &clap::Command,
+    entry: &Entry,
+    cli: &[OsString],
+) -> Result<Vec<OsString>> {
+    let key = entry.key.replace('_', "-");
+    let reserved = ["config", "help", "version"].contains(&key.as_str());
+    let arg = cmd
+        .get_arguments()
+        .filter(|_| !reserved)
+        .find(|arg| arg.get_long() == Some(&key) || (arg.is_positional() && arg.get_id() == key));
+    let Some(arg) = arg else {
+        bail!(
+            "{}: unknown option '{}' for '{}'",
+            path.display(),
+            entry.key,
+            cmd.get_name()
+        );
+    };
+    let repeated =
+        matches!(arg.get_action(), ArgAction::Append) || arg.is_multiple_occurrences_set();
+    // repeated options add up, the command line ones replace the file's
+    if repeated && on_command_line(arg, cli) {
+        return Ok(Vec::new());
+    }
+    let flag = format!("--{}", key);
+    let values = match (&entry.value, arg.is_takes_value_set()) {
+        (Value::Bool(true), false) => return Ok(vec![flag.into()]),
+        (Value::Bool(false), false) => return Ok(Vec::new()),
+        (_, false) => bail!(
+            "{}: '{}' is a flag, expected true or false",
+            path.display(),
+            entry.key
+        ),
+        (Value::Bool(b), true) => vec![b.to_string()],
+        (Value::Scalar(s), true) => vec![s.to_string()],
+        (Value::List(items), true) => items.iter().map(Scalar::to_string).collect(),
+    };
+    Ok(values
+        .into_iter()
+        .flat_map(|value| {
+            if arg.is_positional() {
+                vec![value.into()]
+            } else {
+                vec![flag.clone().into(), value.into()]
+            }
+        })
+        .collect())
+}
+
+/// Command line arguments of the `--config` file in `argv`, if any,
+/// followed by `argv` itself
+pub fn expand(argv: Vec<OsString>) -> Result<Vec<OsString>> {
+    let path = argv.iter().enumerate().find_map(|(i, arg)| {
+        let arg = arg.to_str()?;
+        match arg.strip_prefix("--config") {
+            Some("") => argv.get(i + 1).cloned(),
+            Some(path) => path.strip_prefix('=').map(OsString::from),
+            None => None,
+        }
+    });
+    let Some(path) = path else {
+        return Ok(argv);
+    };
+    let path = Path::new(&path);
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("reading config file '{}'", path.display()))?;
+    let entries = match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => entries(&text, false),
+        Some("yaml" | "yml") => entries(&text, true),
+        _ => bail!(
+            "config file '{}' must be .toml, .yaml or .yml",
+            path.display()
+        ),
+    }
+    .with_context(|| format!("parsing config file '{}'", path.display()))?;
+
+    let mut cmd = Cmd::command();
+    cmd.build();
+    let subcommand = argv.get(1).and_then(|a| a.to_str()).unwrap_or_default();
+    let Some(install) = cmd.find_subcommand(subcommand) else {
+        return Ok(argv);
+    };
+    let cli = &argv[2..];
+    let mut args = argv[..2].to_vec();
+    let mut images: Option<(&str, Vec<OsString>)> = None;
+    for entry in &entries {
+        match &entry.section {
+            None => args.extend(entry_args(path, install, entry, cli)?),
+            Some(section) => {
+                let Some(sub) = install.find_subcommand(section) else {
+                    bail!(
+                        "{}: unknown section '{}', expected one of: {}",
+                        path.display(),
+                        section,
+                        install
+                            .get_subcommands()
+                            .map(|s| s.get_name())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                };
+                match &mut images {
+                    Some((name, _)) if *name != sub.get_name() => bail!(
+                        "{}: only one images section can be given, found '{}' and '{}'",
+                        path.display(),
+                        name,
+                        section
+                    ),
+                    _ => (),
+                }
+                let (_, section_args) = images.get_or_insert((sub.get_name(), Vec::new()));
+                section_args.extend(entry_args(path, sub, entry, cli)?);
+            }
+        }
+    }
+    match images {
+        None => args.extend_from_slice(cli),
+        Some((name, section_args)) => match cli.iter().position(|a| a == name) {
+            // an images subcommand of the command line wins over the file's
+            Some(i) => {
+                args.extend_from_slice(&cli[..=i]);
+                args.extend(section_args);
+                args.extend_from_slice(&cli[i + 1..]);
+            }
+            None => {
+                args.extend_from_slice(cli);
+                if !install
+                    .get_subcommands()
+                    .any(|s| cli.iter().any(|a| a == s.get_name()))
+                {
+                    args.push(name.into());
+                    args.extend(section_args);
+                }
+            }
+        },
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::Parser;
+
+    fn parse(path: &Path, args: &[&str]) -> crate::cmdline::InstallConfig {
+        let argv = ["zvmhelper", "install", "--config", path.to_str().unwrap()]
+            .iter()
+            .chain(args)
+            .map(OsString::from)
+            .collect();
+        Cmd::try_parse_from(expand(argv).unwrap()).unwrap().config()
+    }
+
+    #[test]
+    fn config_formats() {
+        let toml = r#"
+# site defaults
+zvm = "guest1"
+ignition = "http://srv/ign.json" # served by the bastion
+dns = ["10.0.0.1", "10.0.0.2"]
+check-network = true
+retries = 5
+
+[live-images]
+kernel = "http://srv/kernel"
+initrd = "http://srv/initrd"
+rootfs = "http://srv/rootfs"
+"#;
+        let yaml = r#"
+zvm: guest1
+ignition: "http://srv/ign.json"
+dns:
+  - 10.0.0.1
+  - 10.0.0.2
+check-network: true
+retries: 5
+live-images:
+  kernel: http://srv/kernel
+  initrd: http://srv/initrd
+  rootfs: http://srv/rootfs
+"#;
+        assert_eq!(entries(toml, false).unwrap(), entries(yaml, true).unwrap());
+
+        // what a line based subset would misparse
+        let inline = r#"
+zvm = "guest\u0031"
+live-images = { kernel = "http://srv/kernel", initrd = """
+http://srv/initrd""", rootfs = 'http://srv/rootfs' }
+"#;
+        let flow = "zvm: guest1\nlive-images: {kernel: 'http://srv/kernel',\n    initrd: http://srv/initrd}\nlive-images-ignored: []\n";
+        let parsed = entries(inline, false).unwrap();
+        assert_eq!(parsed[0].section.as_deref(), Some("live-images"));
+        assert_eq!(
+            parsed[1].value,
+            Value::Scalar(Scalar::String("http://srv/kernel".to_string()))
+        );
+        assert_eq!(
+            parsed[3],
+            Entry {
+                section: None,
+                key: "zvm".to_string(),
+                value: Value::Scalar(Scalar::String("guest1".to_string())),
+            }
+        );
+        let parsed = entries(flow, true).unwrap();
+        assert_eq!(parsed.len(), 4);
+        assert!(entries("", true).unwrap().is_empty());
+        assert!(entries("zvm = guest1", false).is_err());
+
+        let dir = std::env::temp_dir().join(format!("zvmhelper-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("zvm.toml");
+        std::fs::write(&path, toml).unwrap();
+        let cfg = parse(&path, &[]);
+        assert_eq!(cfg.zvm, "guest1");
+        assert_eq!(
+            cfg.nameservers(),
+            ["10.0.0.1", "10.0.0.2"].map(|ns| ns.parse::<std::net::IpAddr>().unwrap())
+        );
+        assert!(cfg.check_network);
+        assert_eq!(cfg.retries, 5);
+        assert_eq!(
+            cfg.images.live().unwrap().kernel.as_str(),
+            "http://srv/kernel"
+        );
+
+        let cfg = parse(
+            &path,
+            &[
+                "-z",
+                "guest2",
+                "--dns",
+                "10.0.0.3",
+                "live-images",
+                "--kernel",
+                "http://other/kernel",
+            ],
+        );
+        assert_eq!(cfg.zvm, "guest2");
+        assert_eq!(
+            cfg.nameservers(),
+            ["10.0.0.3".parse::<std::net::IpAddr>().unwrap()]
+        );
+        assert_eq!(
+            cfg.images.live().unwrap().kernel.as_str(),
+            "http://other/kernel"
+        );
+        assert_eq!(
+            cfg.images.live().unwrap().initrd.as_str(),
+            "http://srv/initrd"
+        );
+
+        std::fs::write(&path, "zvm = \"guest1\"\nznet_typo = \"0.0.bdf0\"\n").unwrap();
+        let argv = vec![
+            "zvmhelper".into(),
+            "install".into(),
+            "--config".into(),
+            path.clone().into(),
+        ];
+        let err = expand(argv).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{}: unknown option 'znet_typo' for 'install'",
+                path.display()
+            )
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+/// Failures callers can tell apart. They are carried by the `anyhow`
+/// errors the functions return, [`category`] finds them.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("downloading '{url}' failed after {attempts} attempt{}", if *.attempts == 1 { "" } else { "s" })]
+    DownloadFailed { url: String, attempts: u32 },
+    #[error("SHA256 mismatch for '{}': expected {expected}, got {actual}", .path.display())]
+    ChecksumMismatch {
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+    /// `stderr` also holds the CP message vmcp prints on stdout
+    #[error("{cmd} failed with {status}{}", if .stderr.is_empty() { String::new() } else { format!(": {}", .stderr) })]
+    CommandFailed {
+        cmd: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+    #[error("{0}")]
+    InvalidConfig(String),
+    #[error("guest '{zvm}' is not logged on, XAUTOLOG it first: 'vmcp xautolog {zvm}' ({msg})")]
+    GuestNotLoggedOn { zvm: String, msg: String },
+    #[error("--deadline of {}s exceeded during the '{stage}' stage", .deadline.as_secs())]
+    DeadlineExceeded { deadline: Duration, stage: String },
+}
+
+impl Error {
+    /// Exit status of the `zvmhelper` binary failing with it
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::InvalidConfig(_) => 2,
+            Self::DownloadFailed { .. } => 3,
+            Self::ChecksumMismatch { .. } => 4,
+            Self::CommandFailed { .. } => 5,
+            Self::GuestNotLoggedOn { .. } => 6,
+            // as timeout(1)
+            Self::DeadlineExceeded { .. } => 124,
+        }
+    }
+}
+
+/// The [`Error`] of `err`, if it has one
+pub fn category(err: &anyhow::Error) -> Option<&Error> {
+    err.downcast_ref::<Error>()
+        .or_else(|| err.chain().find_map(|cause| cause.downcast_ref::<Error>()))
+}
+
+/// Exit status of the `zvmhelper` binary failing with `err`, 1 when it
+/// has no [`Error`]
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    category(err).map_or(1, Error::exit_code)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn categories() {
+        let err = anyhow::Error::new(Error::InvalidConfig("no --zvm".into()));
+        assert_eq!(err.to_string(), "no --zvm");
+        assert_eq!(exit_code(&err), 2);
+
+        let err = Err::<(), _>(anyhow::anyhow!("connection reset"))
+            .context(Error::DownloadFailed {
+                url: "http://srv/kernel".into(),
+                attempts: 1,
+            })
+            .context("staging the images")
+            .unwrap_err();
+        assert!(matches!(
+            category(&err),
+            Some(Error::DownloadFailed { attempts: 1, .. })
+        ));
+        assert_eq!(
+            format!("{:#}", err),
+            "staging the images: downloading 'http://srv/kernel' failed after 1 attempt: connection reset"
+        );
+        assert_eq!(exit_code(&err), 3);
+        assert_eq!(exit_code(&anyhow::anyhow!("other")), 1);
+
+        let err = anyhow::Error::new(Error::DeadlineExceeded {
+            deadline: Duration::from_secs(1800),
+            stage: "punch".into(),
+        });
+        assert_eq!(
+            err.to_string(),
+            "--deadline of 1800s exceeded during the 'punch' stage"
+        );
+        assert_eq!(exit_code(&err), 124);
+    }
+}
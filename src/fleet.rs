@@ -0,0 +1,225 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::cmdline::{parse_ip, InstallConfig, OutputFormat};
+use crate::config::ZfcpPath;
+use crate::ignition;
+use crate::images::{download_images, write_manifest, Staged};
+use crate::ipl::{check_tools, ipl_zvm_guests};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::read_to_string;
+use std::path::Path;
+
+/// Per-guest settings of a fleet manifest
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Guest {
+    pub zvm: String,
+    pub ip: Option<String>,
+    pub hostname: Option<String>,
+    pub nic: Option<String>,
+    pub dasd: Option<String>,
+    pub edev: Option<String>,
+    pub scsi: Option<String>,
+    pub mp: Option<Vec<String>>,
+}
+
+impl Guest {
+    /// Install configuration of this guest, based on `base`
+    pub fn apply(&self, base: &InstallConfig) -> Result<InstallConfig> {
+        let mut cfg = base.clone();
+        cfg.zvm = self.zvm.clone();
+        if let Some(ip) = &self.ip {
+            // the guest's own address is on its first interface
+            cfg.ip[0] = parse_ip(ip).map_err(|e| anyhow!(e))?;
+            cfg.dhcp = false;
+        }
+        if self.hostname.is_some() {
+            cfg.hostname = self.hostname.clone();
+        }
+        if self.nic.is_some() {
+            cfg.nic = self.nic.clone();
+        }
+        let targets = [&self.dasd, &self.edev, &self.scsi]
+            .iter()
+            .filter(|t| t.is_some())
+            .count()
+            + self.mp.iter().count();
+        if targets > 1 {
+            bail!("more than one disk target");
+        } else if targets == 1 {
+            cfg.target = None;
+            cfg.disk = Vec::new();
+            cfg.dasd = self.dasd.clone();
+            cfg.edev = self.edev.clone();
+            cfg.scsi = self.scsi.clone();
+            cfg.mp = self
+                .mp
+                .as_ref()
+                .map(|mp| {
+                    mp.iter()
+                        .map(|p| p.parse())
+                        .collect::<Result<Vec<ZfcpPath>>>()
+                })
+                .transpose()?;
+        }
+        cfg.validate()?;
+        Ok(cfg)
+    }
+}
+
+/// Reads the guests of a fleet manifest, a JSON list of `Guest`
+pub fn load(manifest: &Path, base: &InstallConfig) -> Result<Vec<InstallConfig>> {
+    let json = read_to_string(manifest)
+        .with_context(|| format!("reading fleet manifest '{}'", manifest.display()))?;
+    let guests: Vec<Guest> = serde_json::from_str(&json)
+        .with_context(|| format!("parsing fleet manifest '{}'", manifest.display()))?;
+    guests
+        .iter()
+        .map(|guest| {
+            guest
+                .apply(base)
+                .with_context(|| format!("guest '{}'", guest.zvm))
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+pub struct GuestReport {
+    pub zvm: String,
+    pub error: Option<String>,
+}
+
+/// Staging and per-guest results of a fleet install
+#[derive(Debug, Default, Serialize)]
+pub struct FleetReport {
+    pub staged: Vec<Staged>,
+    pub staging_error: Option<String>,
+    pub guests: Vec<GuestReport>,
+}
+
+impl FleetReport {
+    fn print(&self, cfg: &InstallConfig) {
+        if cfg.output == OutputFormat::Json {
+            match serde_json::to_string_pretty(self) {
+                Ok(json) => println!("{}", json),
+                Err(e) => eprintln!("Serializing fleet report: {}", e),
+            }
+            return;
+        }
+        match &self.staging_error {
+            Some(e) => println!("Staging failed: {}", e),
+            None => {
+                let bytes: u64 = self.staged.iter().map(|s| s.downloaded).sum();
+                println!(
+                    "Staged {} images, {} bytes downloaded",
+                    self.staged.len(),
+                    bytes
+                );
+            }
+        }
+        for guest in &self.guests {
+            match &guest.error {
+                Some(e) => println!("\t{}: failed: {}", guest.zvm, e),
+                None => println!("\t{}: ready, IPL it with '#cp ipl c'", guest.zvm),
+            }
+        }
+    }
+}
+
+/// Stages the images once and punches them to every guest of `manifest`
+pub fn install(cfg: &InstallConfig, manifest: &Path) -> Result<()> {
+    if let Some(path) = cfg.ignition_path() {
+        bail!(
+            "the local Ignition config '{}' can't be served to a fleet, give its URL",
+            path.display()
+        );
+    }
+    let guests = load(manifest, cfg)?;
+    check_tools(cfg)?;
+    ignition::write_merged(cfg)?;
+    if cfg.check_ignition {
+        ignition::check(cfg)?;
+    } else if !cfg.no_validate_ignition {
+        ignition::validate(cfg)?;
+    }
+    let mut report = FleetReport::default();
+    let staged = download_images(cfg).and_then(|staged| {
+        if let Some(path) = &cfg.manifest_out {
+            write_manifest(path, &staged)?;
+        }
+        Ok(staged)
+    });
+    match staged {
+        Ok(staged) => report.staged = staged,
+        Err(e) => {
+            report.staging_error = Some(format!("{:#}", e));
+            report.print(cfg);
+            return Err(e);
+        }
+    }
+    let results = ipl_zvm_guests(cfg, &guests, cfg.fleet_parallel)?;
+    let failed = results.iter().filter(|r| r.is_err()).count();
+    report.guests = guests
+        .iter()
+        .zip(results)
+        .map(|(guest, result)| GuestReport {
+            zvm: guest.zvm.clone(),
+            error: result.err().map(|e| format!("{:#}", e)),
+        })
+        .collect();
+    report.print(cfg);
+    if failed > 0 {
+        bail!("{} of {} guests failed", failed, guests.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cmdline::test::install;
+
+    fn base() -> InstallConfig {
+        install(&["--dasd", "0.0.1000"]).unwrap()
+    }
+
+    #[test]
+    fn guests() {
+        let json = r#"[
+            {"zvm": "guest1", "ip": "10.0.0.2::10.0.0.1:24:guest1:enc600:none"},
+            {"zvm": "guest2", "scsi": "0.0.1900,0x5005,0x4001"}
+        ]"#;
+        let guests: Vec<Guest> = serde_json::from_str(json).unwrap();
+        let guest1 = guests[0].apply(&base()).unwrap();
+        assert_eq!(guest1.zvm, "guest1");
+        assert_eq!(
+            guest1.ip[0].to_string(),
+            "10.0.0.2::10.0.0.1:255.255.255.0:guest1:enc600:none"
+        );
+        assert_eq!(guest1.dasd.as_deref(), Some("0.0.1000"));
+        let guest2 = guests[1].apply(&base()).unwrap();
+        assert_eq!(guest2.dasd, None);
+        assert_eq!(guest2.scsi.as_deref(), Some("0.0.1900,0x5005,0x4001"));
+    }
+
+    #[test]
+    fn invalid_guests() {
+        let parse = |json: &str| serde_json::from_str::<Vec<Guest>>(json);
+        assert!(parse(r#"[{"zvm": "guest1", "ipaddr": "dhcp"}]"#).is_err());
+        let guests = parse(r#"[{"zvm": "g", "ip": "10.0.0.2::10.1.0.1:24::enc600:none"}]"#);
+        assert!(guests.unwrap()[0].apply(&base()).is_err());
+        let guests = parse(r#"[{"zvm": "g", "dasd": "0.0.1000", "edev": "0.0.2000"}]"#);
+        assert!(guests.unwrap()[0].apply(&base()).is_err());
+    }
+}
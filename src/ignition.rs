@@ -0,0 +1,636 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::cmdline::{CoreOS, Images, InstallConfig};
+use crate::images::{fetch, image_dir};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use log::{info, warn};
+use reqwest::Url;
+use serde_json::Value;
+use std::path::PathBuf;
+
+type Version = (u32, u32, u32);
+
+/// Ignition spec versions accepted by CoreOS releases, the first entry
+/// with a release not newer than the requested one applies
+const FCOS: &[(Version, Version, Version)] = &[
+    ((41, 0, 0), (3, 0, 0), (3, 5, 0)),
+    ((37, 0, 0), (3, 0, 0), (3, 4, 0)),
+    ((34, 0, 0), (3, 0, 0), (3, 3, 0)),
+    ((0, 0, 0), (3, 0, 0), (3, 2, 0)),
+];
+const RHCOS: &[(Version, Version, Version)] = &[
+    ((4, 13, 0), (3, 0, 0), (3, 4, 0)),
+    ((4, 8, 0), (3, 0, 0), (3, 2, 0)),
+    ((4, 6, 0), (3, 0, 0), (3, 1, 0)),
+    ((0, 0, 0), (2, 2, 0), (2, 2, 0)),
+];
+
+fn parse_version(s: &str) -> Result<Version> {
+    let mut parts = s.split('.').map(|p| {
+        p.parse::<u32>()
+            .map_err(|_| anyhow!("'{}' is not a release version", s))
+    });
+    let major = parts.next().unwrap_or_else(|| Ok(0))?;
+    let minor = parts.next().unwrap_or(Ok(0))?;
+    let patch = parts.next().unwrap_or(Ok(0))?;
+    Ok((major, minor, patch))
+}
+
+/// CoreOS release of a `Build` version, RHCOS uses `413` or `413.92` for
+/// 4.13, the RHEL version following the dot
+fn release(variant: &CoreOS, version: &str) -> Result<Version> {
+    match variant {
+        CoreOS::FCOS => parse_version(version),
+        CoreOS::RHCOS => {
+            let ocp = version.split('.').next().unwrap_or_default();
+            if ocp.len() > 1 {
+                let (major, minor) = ocp.split_at(1);
+                parse_version(&format!("{}.{}", major, minor))
+            } else {
+                parse_version(ocp)
+            }
+            .map_err(|_| anyhow!("'{}' is not a release version", version))
+        }
+    }
+}
+
+fn show((major, minor, patch): Version) -> String {
+    format!("{}.{}.{}", major, minor, patch)
+}
+
+/// `ignition.version` of an Ignition config
+pub fn spec_version(config: &[u8]) -> Result<String> {
+    let json: serde_json::Value =
+        serde_json::from_slice(config).context("Ignition config is not valid JSON")?;
+    json.pointer("/ignition/version")
+        .and_then(|v| v.as_str())
+        .map(<_>::to_string)
+        .context("Ignition config has no 'ignition.version'")
+}
+
+/// Checks that `spec` is supported by the given CoreOS release
+pub fn check_supported(variant: &CoreOS, version: &str, spec: &str) -> Result<()> {
+    ensure!(
+        !spec.contains('-'),
+        "Ignition spec {} is experimental and not supported by any release",
+        spec
+    );
+    let spec = parse_version(spec)?;
+    let release = release(variant, version)?;
+    let table = match variant {
+        CoreOS::FCOS => FCOS,
+        CoreOS::RHCOS => RHCOS,
+    };
+    let (_, min, max) = table
+        .iter()
+        .find(|(since, _, _)| *since <= release)
+        .expect("tables end with a catch-all entry");
+    if spec < *min || spec > *max {
+        bail!(
+            "Ignition spec {} is not supported by {:?} {}, expected {} to {}",
+            show(spec),
+            variant,
+            version,
+            show(*min),
+            show(*max)
+        );
+    }
+    Ok(())
+}
+
+/// Deep-merges `fragment` into `base`: objects are merged key by key,
+/// lists are appended as Ignition does, differing values are conflicts
+fn merge(base: &mut Value, fragment: Value, path: &str) -> Result<()> {
+    match (base, fragment) {
+        (Value::Object(base), Value::Object(fragment)) => {
+            for (key, value) in fragment {
+                let path = format!("{}.{}", path, key);
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value, &path)?,
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(base), Value::Array(fragment)) => base.extend(fragment),
+        (base, fragment) if *base == fragment => (),
+        (base, fragment) => bail!(
+            "conflicting values for '{}': {} and {}",
+            path.trim_start_matches('.'),
+            base,
+            fragment
+        ),
+    }
+    Ok(())
+}
+
+/// Merges the `--ignition-file` fragments in order into a single config
+pub fn merge_files(paths: &[PathBuf]) -> Result<Value> {
+    let mut merged = Value::Object(Default::default());
+    for path in paths {
+        let json = std::fs::read(path).with_context(|| format!("reading '{}'", path.display()))?;
+        let fragment = serde_json::from_slice(&json)
+            .with_context(|| format!("'{}' is not valid JSON", path.display()))?;
+        merge(&mut merged, fragment, "")
+            .with_context(|| format!("merging '{}'", path.display()))?;
+    }
+    spec_version(merged.to_string().as_bytes()).context("merged Ignition config")?;
+    Ok(merged)
+}
+
+/// SSH public key types accepted by `--ssh-key`
+const KEY_TYPES: &[&str] = &[
+    "ssh-ed25519",
+    "ssh-rsa",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "ecdsa-sha2-nistp521",
+    "sk-ssh-ed25519@openssh.com",
+    "sk-ecdsa-sha2-nistp256@openssh.com",
+];
+
+fn base64(s: &str) -> Option<Vec<u8>> {
+    let mut bits = 0u32;
+    let mut n = 0;
+    let mut out = Vec::new();
+    for c in s.trim_end_matches('=').bytes() {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = bits << 6 | v as u32;
+        n += 6;
+        if n >= 8 {
+            n -= 8;
+            out.push((bits >> n) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// Checks an `authorized_keys` line: a known key type followed by the
+/// base64 key blob, which starts with the same type
+fn parse_key(line: &str) -> Result<String> {
+    let mut fields = line.split_whitespace();
+    let (kind, blob) = match (fields.next(), fields.next()) {
+        (Some(kind), Some(blob)) => (kind, blob),
+        _ => bail!("'{}' is not an SSH public key", line),
+    };
+    ensure!(
+        KEY_TYPES.contains(&kind),
+        "unsupported SSH key type '{}', expected one of {}",
+        kind,
+        KEY_TYPES.join(", ")
+    );
+    let blob = base64(blob).with_context(|| format!("{} key is not base64", kind))?;
+    let embedded = blob
+        .get(..4)
+        .map(|len| u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize)
+        .and_then(|len| blob.get(4..4 + len));
+    ensure!(
+        embedded == Some(kind.as_bytes()),
+        "{} key data is not of an {} key",
+        kind,
+        kind
+    );
+    Ok(line.trim().to_string())
+}
+
+/// Public keys of `--ssh-key`, given inline or as `authorized_keys` files
+pub fn ssh_keys(values: &[String]) -> Result<Vec<String>> {
+    let mut keys = Vec::new();
+    for value in values {
+        let kind = value.split_whitespace().next().unwrap_or_default();
+        if KEY_TYPES.contains(&kind) {
+            keys.push(parse_key(value)?);
+            continue;
+        }
+        let file = std::fs::read_to_string(value)
+            .with_context(|| format!("'{}' is neither an SSH public key nor a key file", value))?;
+        for (n, line) in file.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            keys.push(parse_key(line).with_context(|| format!("{}:{}", value, n + 1))?);
+        }
+    }
+    Ok(keys)
+}
+
+/// Adds `keys` to the `core` user of `config`
+fn add_ssh_keys(config: &mut Value, keys: Vec<String>) -> Result<()> {
+    let users = config
+        .as_object_mut()
+        .context("Ignition config is not an object")?
+        .entry("passwd")
+        .or_insert_with(|| serde_json::json!({}))
+        .as_object_mut()
+        .context("'passwd' is not an object")?
+        .entry("users")
+        .or_insert_with(|| serde_json::json!([]))
+        .as_array_mut()
+        .context("'passwd.users' is not a list")?;
+    let core = match users.iter().position(|u| u["name"] == "core") {
+        Some(n) => &mut users[n],
+        None => {
+            users.push(serde_json::json!({"name": "core"}));
+            users.last_mut().expect("just pushed")
+        }
+    };
+    let authorized = core
+        .as_object_mut()
+        .context("'core' user is not an object")?
+        .entry("sshAuthorizedKeys")
+        .or_insert_with(|| serde_json::json!([]))
+        .as_array_mut()
+        .context("'sshAuthorizedKeys' of 'core' is not a list")?;
+    for key in keys {
+        if !authorized.iter().any(|k| *k == key) {
+            authorized.push(Value::String(key));
+        }
+    }
+    Ok(())
+}
+
+/// Adds an encrypted `root` bound to the `--tang-url` servers, and to a
+/// TPM2 device with `--tpm2`, as Butane's `boot_device.luks` does. The
+/// config is raised to spec 3.2.0, the first one with LUKS support.
+fn add_luks(config: &mut Value, cfg: &InstallConfig) -> Result<()> {
+    let version = config
+        .pointer("/ignition/version")
+        .and_then(Value::as_str)
+        .context("Ignition config has no 'ignition.version'")?;
+    let spec = parse_version(version)?;
+    ensure!(
+        spec >= (3, 0, 0),
+        "Ignition spec {} has no LUKS support, expected 3.2.0 or newer",
+        version
+    );
+    if spec < (3, 2, 0) {
+        config["ignition"]["version"] = Value::from("3.2.0");
+    }
+    if let Some(luks) = config.pointer("/storage/luks").and_then(Value::as_array) {
+        ensure!(
+            !luks.iter().any(|l| l["name"] == "root"),
+            "the Ignition config already sets up a LUKS 'root'"
+        );
+    }
+    let tang: Vec<Value> = cfg
+        .tang_url
+        .iter()
+        .zip(&cfg.tang_thumbprint)
+        .map(|(url, thumbprint)| serde_json::json!({"url": url.as_str(), "thumbprint": thumbprint}))
+        .collect();
+    let fragment = serde_json::json!({
+        "storage": {
+            "luks": [{
+                "name": "root",
+                "device": "/dev/disk/by-partlabel/root",
+                "label": "luks-root",
+                "wipeVolume": true,
+                "clevis": {"tpm2": cfg.tpm2, "tang": tang},
+            }],
+            "filesystems": [{
+                "device": "/dev/mapper/root",
+                "format": "xfs",
+                "label": "root",
+                "wipeFilesystem": true,
+            }],
+        },
+    });
+    merge(config, fragment, "")
+}
+
+/// Config written for the guest: a local `--ignition` merged with the
+/// `--ignition-file` fragments, or one merging the `--ignition` URL, with
+/// the `--ssh-key` keys and `--luks` root added. None when the guest
+/// fetches `--ignition` as is.
+pub fn config(cfg: &InstallConfig) -> Result<Option<Value>> {
+    let local = cfg.ignition_path();
+    let mut config = if local.is_some() || !cfg.ignition_file.is_empty() {
+        let files: Vec<PathBuf> = local
+            .into_iter()
+            .chain(cfg.ignition_file.iter().cloned())
+            .collect();
+        merge_files(&files)?
+    } else if cfg.ignition_additions() {
+        serde_json::json!({
+            "ignition": {
+                "version": "3.0.0",
+                "config": {"merge": [{"source": cfg.ignition_source()}]},
+            },
+        })
+    } else {
+        return Ok(None);
+    };
+    if !cfg.ssh_key.is_empty() {
+        add_ssh_keys(&mut config, ssh_keys(&cfg.ssh_key)?).context("adding --ssh-key")?;
+    }
+    if cfg.luks {
+        add_luks(&mut config, cfg).context("adding --luks")?;
+    }
+    Ok(Some(config))
+}
+
+/// Path of the merged Ignition config of `zvm` in the image directory
+pub fn merged_path(zvm: &str) -> Result<PathBuf> {
+    Ok(image_dir()?.join(format!("ignition.{}.ign", zvm)))
+}
+
+/// Writes the local, merged or `--ssh-key` config, if any, to be served
+/// as the config `--ignition` points to
+pub fn write_merged(cfg: &InstallConfig) -> Result<Option<PathBuf>> {
+    let Some(merged) = config(cfg)? else {
+        return Ok(None);
+    };
+    let path = merged_path(&cfg.zvm)?;
+    let json = serde_json::to_string_pretty(&merged).context("serializing Ignition config")?;
+    std::fs::write(&path, json + "\n").with_context(|| format!("writing '{}'", path.display()))?;
+    let what = match (cfg.ignition_path(), cfg.ignition_file.len()) {
+        (Some(local), 0) => format!("Staged {}", local.display()),
+        (Some(local), n) => format!("Merged {} and {} Ignition fragments", local.display(), n),
+        (None, 0) => format!(
+            "Merged {} with the --ssh-key and --luks additions",
+            cfg.ignition_source()
+        ),
+        (None, n) => format!("Merged {} Ignition fragments", n),
+    };
+    info!("{} into {}", what, path.display());
+    if cfg.serve_addr().is_none() {
+        info!("It must be served at {}", cfg.ignition);
+    }
+    Ok(Some(path))
+}
+
+/// Fetches the `--ignition` URL, or builds the merged config. None for
+/// URLs of schemes the helper can't fetch, only the guest can.
+fn load(cfg: &InstallConfig) -> Result<Option<Vec<u8>>> {
+    if cfg.ignition_file.is_empty() && cfg.ignition_path().is_none() {
+        let url = Url::parse(cfg.ignition_source())
+            .with_context(|| format!("'{}' is not a valid URL", cfg.ignition_source()))?;
+        if !matches!(url.scheme(), "http" | "https" | "file") {
+            return Ok(None);
+        }
+        return fetch(cfg, &url).map(Some);
+    }
+    let config = config(cfg)?.context("no merged Ignition config")?;
+    Ok(Some(serde_json::to_vec(&config)?))
+}
+
+/// Checks that the Ignition config is JSON with an `ignition.version`,
+/// before anything is punched to the guest
+pub fn validate(cfg: &InstallConfig) -> Result<()> {
+    let source = cfg.ignition_source();
+    let config = load(cfg).with_context(|| format!("invalid Ignition config '{}'", source))?;
+    match config {
+        Some(config) => spec_version(&config)
+            .map(|_| ())
+            .with_context(|| format!("invalid Ignition config '{}'", source)),
+        None => {
+            warn!(
+                "Ignition config '{}' not validated, it can't be fetched here",
+                source
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Fetches the Ignition config, or reads the merged one, and checks its
+/// spec version against the CoreOS build being installed
+pub fn check(cfg: &InstallConfig) -> Result<()> {
+    let config = load(cfg)?.with_context(|| {
+        format!(
+            "Ignition config '{}' can't be fetched",
+            cfg.ignition_source()
+        )
+    })?;
+    let spec = spec_version(&config)?;
+    match &cfg.images {
+        Images::Artifacts(build) => check_supported(&build.variant, &build.version, &spec),
+        Images::LiveImages(_) | Images::Oci(_) => {
+            warn!(
+                "Ignition spec {} not checked, the CoreOS release of live images is unknown",
+                spec
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cmdline::test::install_with;
+
+    #[test]
+    fn spec_versions() {
+        assert_eq!(
+            spec_version(br#"{"ignition": {"version": "3.4.0"}}"#).unwrap(),
+            "3.4.0"
+        );
+        assert!(spec_version(br#"{"ignition": {}}"#).is_err());
+        assert!(spec_version(b"ignition").is_err());
+    }
+
+    #[test]
+    fn merged_fragments() {
+        let mut base = serde_json::json!({
+            "ignition": {"version": "3.4.0"},
+            "passwd": {"users": [{"name": "core"}]},
+        });
+        let role = serde_json::json!({
+            "ignition": {"version": "3.4.0"},
+            "passwd": {"users": [{"name": "admin"}]},
+            "storage": {"files": [{"path": "/etc/role"}]},
+        });
+        merge(&mut base, role, "").unwrap();
+        assert_eq!(
+            base,
+            serde_json::json!({
+                "ignition": {"version": "3.4.0"},
+                "passwd": {"users": [{"name": "core"}, {"name": "admin"}]},
+                "storage": {"files": [{"path": "/etc/role"}]},
+            })
+        );
+        let err = merge(
+            &mut base,
+            serde_json::json!({"ignition": {"version": "3.3.0"}}),
+            "",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"conflicting values for 'ignition.version': "3.4.0" and "3.3.0""#
+        );
+    }
+
+    #[test]
+    fn supported_specs() {
+        assert!(check_supported(&CoreOS::FCOS, "37", "3.4.0").is_ok());
+        assert!(check_supported(&CoreOS::FCOS, "37", "3.0.0").is_ok());
+        let err = check_supported(&CoreOS::FCOS, "37", "3.5.0").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Ignition spec 3.5.0 is not supported by FCOS 37, expected 3.0.0 to 3.4.0"
+        );
+        assert!(check_supported(&CoreOS::FCOS, "41", "3.5.0").is_ok());
+        assert!(check_supported(&CoreOS::FCOS, "37", "3.5.0-experimental").is_err());
+        assert!(check_supported(&CoreOS::RHCOS, "413", "3.4.0").is_ok());
+        assert!(check_supported(&CoreOS::RHCOS, "412", "3.4.0").is_err());
+        assert!(check_supported(&CoreOS::RHCOS, "413.92", "3.4.0").is_ok());
+        assert!(check_supported(&CoreOS::RHCOS, "48.84", "3.2.0").is_ok());
+        let err = check_supported(&CoreOS::RHCOS, "48.84", "3.4.0").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Ignition spec 3.4.0 is not supported by RHCOS 48.84, expected 3.0.0 to 3.2.0"
+        );
+        assert_eq!(release(&CoreOS::RHCOS, "45.82").unwrap(), (4, 5, 0));
+        assert!(check_supported(&CoreOS::RHCOS, "45", "3.0.0").is_err());
+    }
+
+    #[test]
+    fn ssh_key_config() {
+        const ED25519: &str =
+            "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIAABAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4f admin@bastion";
+        const RSA: &str = "ssh-rsa AAAAB3NzaC1yc2EAAAADAQABAAAACQAAAQIDBAUGBw==";
+        assert_eq!(parse_key(ED25519).unwrap(), ED25519);
+        assert_eq!(parse_key(RSA).unwrap(), RSA);
+        assert!(parse_key("ssh-ed25519").is_err());
+        assert!(parse_key("ssh-dss AAAAB3NzaC1kc3M=").is_err());
+        assert!(parse_key("ssh-ed25519 not-base64!").is_err());
+        let err =
+            parse_key("ssh-ed25519 AAAAB3NzaC1yc2EAAAADAQABAAAACQAAAQIDBAUGBw==").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ssh-ed25519 key data is not of an ssh-ed25519 key"
+        );
+
+        let file = std::env::temp_dir().join(format!("zvmhelper-keys-{}", std::process::id()));
+        std::fs::write(&file, format!("# admins\n{}\n\n{}\n", ED25519, RSA)).unwrap();
+        let keys = ssh_keys(&[file.to_string_lossy().into_owned(), RSA.to_string()]).unwrap();
+        assert_eq!(keys, [ED25519, RSA, RSA]);
+        std::fs::write(&file, "ssh-rsa AAAA\n").unwrap();
+        let err = ssh_keys(&[file.to_string_lossy().into_owned()]).unwrap_err();
+        assert_eq!(err.to_string(), format!("{}:1", file.display()));
+        std::fs::remove_file(&file).unwrap();
+        assert!(ssh_keys(&["ssh-key".to_string()]).is_err());
+
+        let mut config = serde_json::json!({
+            "ignition": {"version": "3.4.0"},
+            "passwd": {"users": [{"name": "admin"}, {"name": "core", "sshAuthorizedKeys": [RSA]}]},
+        });
+        add_ssh_keys(&mut config, vec![ED25519.to_string(), RSA.to_string()]).unwrap();
+        assert_eq!(
+            config["passwd"]["users"][1],
+            serde_json::json!({"name": "core", "sshAuthorizedKeys": [RSA, ED25519]})
+        );
+        let mut config = serde_json::json!({"ignition": {"version": "3.0.0"}});
+        add_ssh_keys(&mut config, vec![RSA.to_string()]).unwrap();
+        assert_eq!(
+            config["passwd"],
+            serde_json::json!({"users": [{"name": "core", "sshAuthorizedKeys": [RSA]}]})
+        );
+    }
+
+    #[test]
+    fn luks_config() {
+        let cfg = |extra: &[&str]| {
+            let luks = [&["--luks"], extra].concat();
+            install_with("http://srv/ign.json", &luks, &[]).unwrap()
+        };
+        let tang = cfg(&[
+            "--ignition-serve",
+            ":8080",
+            "--tang-url",
+            "http://tang1",
+            "--tang-thumbprint",
+            "abc",
+            "--tpm2",
+        ]);
+        tang.validate().unwrap();
+        let config = config(&tang).unwrap().unwrap();
+        assert_eq!(config["ignition"]["version"], "3.2.0");
+        assert_eq!(
+            config["ignition"]["config"]["merge"][0]["source"],
+            "http://srv/ign.json"
+        );
+        assert_eq!(
+            config["storage"]["luks"][0]["clevis"],
+            serde_json::json!({"tpm2": true, "tang": [{"url": "http://tang1/", "thumbprint": "abc"}]})
+        );
+        assert_eq!(
+            config["storage"]["filesystems"][0]["device"],
+            "/dev/mapper/root"
+        );
+
+        let mut base = serde_json::json!({"ignition": {"version": "3.4.0"}});
+        add_luks(&mut base, &tang).unwrap();
+        assert_eq!(base["ignition"]["version"], "3.4.0");
+        let err = add_luks(&mut base, &tang).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the Ignition config already sets up a LUKS 'root'"
+        );
+        let mut old = serde_json::json!({"ignition": {"version": "2.2.0"}});
+        assert!(add_luks(&mut old, &tang).is_err());
+
+        let err = cfg(&["--ignition-serve", ":8080"]).validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--luks needs a key source, --tang-url or --tpm2"
+        );
+        assert!(cfg(&["--ignition-serve", ":8080", "--tpm2"])
+            .validate()
+            .is_err());
+        assert!(
+            cfg(&["--ignition-serve", ":8080", "--tang-url", "http://tang1"])
+                .validate()
+                .is_err()
+        );
+        assert!(
+            cfg(&["--tang-url", "http://tang1", "--tang-thumbprint", "abc"])
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn validate_config() {
+        let file =
+            std::env::temp_dir().join(format!("zvmhelper-validate-{}.ign", std::process::id()));
+        let url = Url::from_file_path(&file).unwrap();
+        let cfg = |ignition: &str| install_with(ignition, &[], &[]).unwrap();
+        std::fs::write(&file, r#"{"ignition": {"version": "3.4.0"}}"#).unwrap();
+        validate(&cfg(url.as_str())).unwrap();
+        std::fs::write(&file, r#"{"ignition": {}}"#).unwrap();
+        let err = validate(&cfg(url.as_str())).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            format!(
+                "invalid Ignition config '{}': merged Ignition config: Ignition config has no 'ignition.version'",
+                url
+            )
+        );
+        std::fs::write(&file, "{").unwrap();
+        assert!(validate(&cfg(url.as_str())).is_err());
+        std::fs::remove_file(&file).unwrap();
+        validate(&cfg("tftp://srv/ign.json")).unwrap();
+    }
+}
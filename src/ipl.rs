@@ -10,150 +10,1669 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::cmdline::{Images, InstallConfig, Live};
-use anyhow::{anyhow, Context, Result};
-use reqwest::Url;
-use std::process::Command;
+use crate::cmdline::{InstallConfig, NeedNet, PunchFormat};
+use crate::error::Error;
+use crate::images::{local_path, punched_initrd};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use log::{debug, error, info, warn};
+use std::env::{split_paths, var_os};
+use std::ffi::OsStr;
+use std::fs::{metadata, read_to_string};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 
+/// Runs a command as `cfg` says, its output is captured and the stderr of
+/// a failing one is part of the error
 macro_rules! runcmd {
-    ($cmd:expr) => (runcmd!($cmd,));
-    ($cmd:expr, $($args:expr),*) => {{
+    ($cfg:expr, $cmd:expr) => (runcmd!($cfg, $cmd,));
+    ($cfg:expr, $cmd:expr, $($args:expr),*) => {{
         let mut cmd = Command::new($cmd);
         $( cmd.arg($args); )*
-        let status = cmd.status().with_context(|| format!("running {:#?}", cmd))?;
-        if !status.success() {
-            Result::Err(anyhow!("{:#?} failed with {}", cmd, status))
-        } else {
-            Result::Ok(())
-        }
+        run($cfg, cmd, true)
     }}
 }
 
+/// Runs a command as `cfg` says, with its output going to ours as it is
+/// printed
+macro_rules! runcmd_stream {
+    ($cfg:expr, $cmd:expr) => (runcmd_stream!($cfg, $cmd,));
+    ($cfg:expr, $cmd:expr, $($args:expr),*) => {{
+        let mut cmd = Command::new($cmd);
+        $( cmd.arg($args); )*
+        run($cfg, cmd, false)
+    }}
+}
+
+/// Runs `cmd` on the `--ssh` host if given, with `--dry-run` it is only
+/// printed
+fn run(cfg: &InstallConfig, cmd: Command, capture: bool) -> Result<()> {
+    let cmd = remote(cfg, cmd);
+    if cfg.dry_run {
+        info!("Would run: {:?}", cmd);
+        return Ok(());
+    }
+    wait(cmd, capture)
+}
+
+/// Runs `cmd` to completion, tracked to be killed on abort
+fn wait(mut cmd: Command, capture: bool) -> Result<()> {
+    debug!("Running {:?}", cmd);
+    if capture {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+    let child = cmd.spawn().with_context(|| format!("running {:#?}", cmd))?;
+    let pid = child.id();
+    track(pid, true);
+    let output = child.wait_with_output();
+    track(pid, false);
+    let output = output.with_context(|| format!("running {:#?}", cmd))?;
+    debug!("{:?} exited with {}", cmd, output.status);
+    if output.status.success() {
+        return Ok(());
+    }
+    // vmcp reports the failed CP command on stderr and the CP message,
+    // like HCP003E, on stdout
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let message: Vec<&str> = vec![stderr.trim(), stdout.trim()]
+        .into_iter()
+        .filter(|s| !s.is_empty())
+        .collect();
+    Err(Error::CommandFailed {
+        cmd: format!("{:#?}", cmd),
+        status: output.status,
+        stderr: message.join("; "),
+    }
+    .into())
+}
+
+/// Common options of ssh and scp, they must not prompt
+const SSH_OPTIONS: [&str; 2] = ["-o", "BatchMode=yes"];
+
+/// `arg` quoted for the remote shell ssh runs commands with
+fn shell_quote(arg: &OsStr) -> String {
+    let arg = arg.to_string_lossy();
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-./:=,@+%".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        return arg.into_owned();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// `cmd` run on `host` over SSH
+fn ssh_command(host: &str, cmd: &Command) -> Command {
+    let line: Vec<String> = std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(shell_quote)
+        .collect();
+    let mut ssh = Command::new("ssh");
+    ssh.args(SSH_OPTIONS)
+        .arg(host)
+        .arg("--")
+        .arg(line.join(" "));
+    ssh
+}
+
+/// `cmd` as it is run: over SSH with `--ssh`, locally otherwise
+pub fn remote(cfg: &InstallConfig, cmd: Command) -> Command {
+    match &cfg.ssh {
+        Some(host) => ssh_command(host, &cmd),
+        None => cmd,
+    }
+}
+
+/// Copies `file` to the `--ssh` host as `name` in its temporary
+/// directory, returns the path to punch
+fn upload(cfg: &InstallConfig, file: &Path, name: &str) -> Result<PathBuf> {
+    let Some(host) = &cfg.ssh else {
+        return Ok(file.to_path_buf());
+    };
+    let target = PathBuf::from(format!("/tmp/zvmhelper-{}-{}", std::process::id(), name));
+    let mut scp = Command::new("scp");
+    scp.arg("-q")
+        .args(SSH_OPTIONS)
+        .arg(file)
+        .arg(format!("{}:{}", host, target.display()));
+    if cfg.dry_run {
+        info!("Would run: {:?}", scp);
+    } else {
+        wait(scp, true).with_context(|| format!("copying '{}' to '{}'", file.display(), host))?;
+    }
+    Ok(target)
+}
+
+/// Commands being run by `runcmd!`, killed when the install is aborted
+static RUNNING: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+fn track(pid: u32, running: bool) {
+    let mut pids = RUNNING.lock().unwrap();
+    if running {
+        pids.push(pid);
+    } else {
+        pids.retain(|p| *p != pid);
+    }
+}
+
+/// Kills the commands being run
+pub fn kill_running() {
+    for pid in RUNNING.lock().unwrap().drain(..) {
+        let _ = Command::new("kill").arg(pid.to_string()).status();
+    }
+}
+
+/// Devices brought online by `enable_vmur_dev()`, and whether they were
+/// on the cio_ignore list before
+static ONLINED: Mutex<Vec<(String, bool)>> = Mutex::new(Vec::new());
+
 pub fn ipl_zvm_guest(cfg: &InstallConfig) -> Result<()> {
-    enable_vmur_dev()?;
-    clear(&cfg.zvm)?;
-    send(cfg)?;
-    println!("Please login to zVM and IPL and manually: '#cp ipl c'");
+    ipl_zvm_guests(cfg, std::slice::from_ref(cfg), 1)?.remove(0)
+}
+
+/// Punches the install files to each of `guests`, at most `parallel` of
+/// them at a time. The vmur devices are set up once for all of them.
+pub fn ipl_zvm_guests(
+    cfg: &InstallConfig,
+    guests: &[InstallConfig],
+    parallel: usize,
+) -> Result<Vec<Result<()>>> {
+    let mut results = Vec::with_capacity(guests.len());
+    if let Err(e) = enable_vmur_dev(cfg) {
+        if let Err(e) = restore_devices(cfg) {
+            error!("Restoring devices: {:#}", e);
+        }
+        return Err(e);
+    }
+    for chunk in guests.chunks(parallel.max(1)) {
+        std::thread::scope(|scope| {
+            let punching: Vec<_> = chunk
+                .iter()
+                .map(|guest| {
+                    scope.spawn(move || {
+                        if !guest.no_check_logon {
+                            check_guest(guest)?;
+                        }
+                        clear(guest).and_then(|_| send(guest))
+                    })
+                })
+                .collect();
+            results.extend(punching.into_iter().map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!("punching thread panicked")))
+            }));
+        });
+    }
+    if results.iter().any(Result::is_err) || cfg.restore_devices {
+        if let Err(e) = restore_devices(cfg) {
+            error!("Restoring devices: {:#}", e);
+        }
+    }
+    Ok(results)
+}
+
+/// Restores the devices state on Ctrl-C
+pub fn install_cleanup_handler(cfg: &InstallConfig) -> Result<()> {
+    let cfg = cfg.clone();
+    ctrlc::set_handler(move || {
+        if let Err(e) = restore_devices(&cfg) {
+            error!("Restoring devices: {:#}", e);
+        }
+        std::process::exit(130);
+    })
+    .context("installing Ctrl-C handler")
+}
+
+/// s390 tools the install runs, with the packages providing them
+const TOOLS: &[(&str, &str)] = &[
+    ("modprobe", "kmod"),
+    ("cio_ignore", "s390utils-base or s390-tools"),
+    ("chccwdev", "s390utils-base or s390-tools"),
+    ("vmcp", "s390utils-base or s390-tools"),
+    ("vmur", "s390utils-base or s390-tools"),
+];
+
+/// Local tools `--ssh` runs
+const SSH_TOOLS: &[(&str, &str)] = &[
+    ("ssh", "openssh-clients or openssh-client"),
+    ("scp", "openssh-clients or openssh-client"),
+];
+
+/// `tools` not found in the directories of `path`
+fn missing_tools(
+    path: &OsStr,
+    tools: &[(&'static str, &'static str)],
+) -> Vec<(&'static str, &'static str)> {
+    tools
+        .iter()
+        .filter(|(tool, _)| !split_paths(path).any(|dir| dir.join(tool).is_file()))
+        .copied()
+        .collect()
+}
+
+/// TOOLS not found on the `--ssh` host
+fn missing_remote_tools(host: &str) -> Result<Vec<(&'static str, &'static str)>> {
+    let names: Vec<&str> = TOOLS.iter().map(|(tool, _)| *tool).collect();
+    let mut sh = Command::new("sh");
+    sh.arg("-c").arg(format!(
+        "for t in {}; do command -v $t >/dev/null || echo $t; done",
+        names.join(" ")
+    ));
+    let output = ssh_command(host, &sh)
+        .output()
+        .with_context(|| format!("running ssh to '{}'", host))?;
+    ensure!(
+        output.status.success(),
+        "ssh to '{}' failed with {}: {}",
+        host,
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let missing: Vec<&str> = stdout.lines().map(str::trim).collect();
+    Ok(TOOLS
+        .iter()
+        .filter(|(tool, _)| missing.contains(tool))
+        .copied()
+        .collect())
+}
+
+/// Missing tools with the packages providing them
+fn describe_missing(missing: &[(&str, &str)]) -> String {
+    let mut packages: Vec<(&str, Vec<&str>)> = Vec::new();
+    for (tool, package) in missing {
+        match packages.iter_mut().find(|(p, _)| p == package) {
+            Some((_, tools)) => tools.push(tool),
+            None => packages.push((package, vec![tool])),
+        }
+    }
+    let missing: Vec<String> = packages
+        .iter()
+        .map(|(package, tools)| format!("{} (from {})", tools.join(", "), package))
+        .collect();
+    missing.join("; ")
+}
+
+/// Checks that the s390 tools are on PATH before anything is downloaded,
+/// on the `--ssh` host if given. A dry run doesn't need them.
+pub fn check_tools(cfg: &InstallConfig) -> Result<()> {
+    if cfg.dry_run {
+        return Ok(());
+    }
+    let path = var_os("PATH").unwrap_or_default();
+    let Some(host) = &cfg.ssh else {
+        let missing = missing_tools(&path, TOOLS);
+        ensure!(
+            missing.is_empty(),
+            "missing tools on PATH: {}",
+            describe_missing(&missing)
+        );
+        return Ok(());
+    };
+    let missing = missing_tools(&path, SSH_TOOLS);
+    ensure!(
+        missing.is_empty(),
+        "missing tools on PATH: {}",
+        describe_missing(&missing)
+    );
+    let missing = missing_remote_tools(host)?;
+    ensure!(
+        missing.is_empty(),
+        "missing tools on PATH of '{}': {}",
+        host,
+        describe_missing(&missing)
+    );
     Ok(())
 }
 
-fn enable_vmur_dev() -> Result<()> {
-    runcmd!("modprobe", "vmur")?;
-    for id in ["c", "d", "e"] {
-        let output = Command::new("cio_ignore")
-            .arg("--is-ignored")
-            .arg(id)
-            .output()
-            .with_context(|| format!("running 'cio_ignore --is-ignored {}'", id))?;
-        let output = String::from_utf8(output.stdout)?;
-        if output.contains("is ignored") {
-            runcmd!("cio_ignore", "--remove", id)?;
+fn is_online(cfg: &InstallConfig, id: &str) -> bool {
+    let online = format!("/sys/bus/ccw/devices/{}/online", id);
+    let state = match cfg.ssh {
+        Some(_) => {
+            let mut cat = Command::new("cat");
+            cat.arg(&online);
+            remote(cfg, cat)
+                .output()
+                .ok()
+                .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+        }
+        None => read_to_string(online).ok(),
+    };
+    state.is_some_and(|state| online_state(&state))
+}
+
+/// Whether the sysfs `online` attribute of a device says it is online
+fn online_state(attr: &str) -> bool {
+    attr.trim() == "1"
+}
+
+/// Whether `cio_ignore --is-ignored` reports the device as ignored, it
+/// prints `Device 0.0.000c is ignored.` or `... is not ignored.`
+fn ignored_state(output: &str) -> bool {
+    output
+        .lines()
+        .any(|line| line.trim().trim_end_matches('.').ends_with(" is ignored"))
+}
+
+/// Brings the vmur devices online, those already online are left as
+/// they are and not restored afterwards
+fn enable_vmur_dev(cfg: &InstallConfig) -> Result<()> {
+    runcmd!(cfg, "modprobe", "vmur")?;
+    for id in cfg.vmur_devices.all() {
+        if is_online(cfg, id) {
+            debug!("Device {} is already online", id);
+            continue;
+        }
+        let mut cmd = Command::new("cio_ignore");
+        cmd.arg("--is-ignored").arg(id);
+        let output = remote(cfg, cmd).output();
+        // a dry run may be done where the s390 tools are missing
+        let ignored = match output {
+            Err(_) if cfg.dry_run => false,
+            output => {
+                let output =
+                    output.with_context(|| format!("running 'cio_ignore --is-ignored {}'", id))?;
+                ignored_state(&String::from_utf8(output.stdout)?)
+            }
+        };
+        if ignored {
+            runcmd!(cfg, "cio_ignore", "--remove", id)?;
+        }
+        if let Err(e) = runcmd!(cfg, "chccwdev", "--online", id) {
+            if !is_online(cfg, id) {
+                return Err(e);
+            }
+            warn!("Device {} came online despite: {:#}", id, e);
+            continue;
+        }
+        if !cfg.dry_run {
+            ONLINED.lock().unwrap().push((id.to_string(), ignored));
+        }
+    }
+    Ok(())
+}
+
+/// Offlines the devices we brought online and puts them back on the
+/// cio_ignore list if they were there
+pub fn restore_devices(cfg: &InstallConfig) -> Result<()> {
+    let mut failed = Vec::new();
+    let onlined: Vec<_> = ONLINED.lock().unwrap().drain(..).collect();
+    for (id, ignored) in onlined.into_iter().rev() {
+        if let Err(e) = runcmd!(cfg, "chccwdev", "--offline", &id) {
+            failed.push(format!("{:#}", e));
+        } else if ignored {
+            if let Err(e) = runcmd!(cfg, "cio_ignore", "--add", &id) {
+                failed.push(format!("{:#}", e));
+            }
         }
-        runcmd!("chccwdev", "--online", id)?;
+    }
+    if !failed.is_empty() {
+        bail!(failed.join("\n"));
     }
     Ok(())
 }
 
-fn clear(zvm: &str) -> Result<()> {
-    runcmd!("vmcp", "sp", "pun", zvm, "rdr")?;
-    runcmd!("vmcp", "pur", zvm, "rdr", "all")
+/// Reader files CP refused to release, the install can't go on until an
+/// operator deals with them
+#[derive(Debug)]
+pub struct ReaderBlocked {
+    pub zvm: String,
+    pub files: Vec<String>,
 }
 
-fn punch(zvm: &str, target: &str, file: &str) -> Result<()> {
-    println!("Copying '{}' to '{}': '{}'", file, zvm, target);
-    runcmd!("vmur", "punch", "-r", "-u", zvm, "-N", target, file)
+impl std::fmt::Display for ReaderBlocked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "reader of '{}' needs manual intervention, these files are held: {}",
+            self.zvm,
+            self.files.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for ReaderBlocked {}
+
+/// Held files in a `q rdr <zvm> all` listing, as `<spoolid> (<hold>)`
+fn held_files(listing: &str) -> Vec<String> {
+    listing
+        .lines()
+        .skip(1)
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .filter(|cols| cols.len() > 6 && cols[6] != "NONE")
+        .map(|cols| format!("{} ({})", cols[1], cols[6]))
+        .collect()
+}
+
+/// Describes what blocks the reader of the guest, if anything can be found
+fn reader_blocked(cfg: &InstallConfig) -> Option<ReaderBlocked> {
+    let zvm = cfg.zvm.as_str();
+    let mut cmd = Command::new("vmcp");
+    cmd.args(["q", "rdr", zvm, "all"]);
+    let output = remote(cfg, cmd).output().ok()?;
+    let files = held_files(&String::from_utf8_lossy(&output.stdout));
+    if files.is_empty() {
+        return None;
+    }
+    Some(ReaderBlocked {
+        zvm: zvm.to_string(),
+        files,
+    })
+}
+
+/// CP error message in a vmcp response, like `HCPCQU045E`
+pub fn cp_error(response: &str) -> Option<&str> {
+    response.lines().map(str::trim).find(|line| {
+        line.len() > 10
+            && line.starts_with("HCP")
+            && line.as_bytes()[9] == b'E'
+            && line[6..9].bytes().all(|b| b.is_ascii_digit())
+    })
+}
+
+/// Checks a `q user <zvm>` response
+fn logged_on(zvm: &str, response: &str) -> Result<()> {
+    match cp_error(response) {
+        None => Ok(()),
+        Some(msg) if msg.contains("not logged on") || msg.starts_with("HCPCQU361E") => {
+            Err(Error::GuestNotLoggedOn {
+                zvm: zvm.to_string(),
+                msg: msg.to_string(),
+            }
+            .into())
+        }
+        Some(msg) if msg.starts_with("HCPCQU003E") => {
+            bail!("guest '{}' does not exist ({})", zvm, msg)
+        }
+        Some(msg) => bail!("querying guest '{}': {}", zvm, msg),
+    }
+}
+
+/// Response of a `vmcp` query, CP errors are in it and not failures
+fn query(cfg: &InstallConfig, args: &[&str]) -> Result<String> {
+    let mut cmd = Command::new("vmcp");
+    cmd.args(args);
+    let output = remote(cfg, cmd)
+        .output()
+        .with_context(|| format!("running 'vmcp {}'", args.join(" ")))?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Checks that the guest is logged on and that its reader can be queried,
+/// the purge and the punch fail cryptically otherwise
+fn check_guest(cfg: &InstallConfig) -> Result<()> {
+    let zvm = cfg.zvm.as_str();
+    if cfg.dry_run {
+        info!("Would check that '{}' is logged on", zvm);
+        return Ok(());
+    }
+    logged_on(zvm, &query(cfg, &["q", "user", zvm])?)?;
+    if let Some(msg) = cp_error(&query(cfg, &["q", "rdr", zvm, "all"])?) {
+        bail!(
+            "reader of '{}' can't be queried, the helper needs CP class D: {}",
+            zvm,
+            msg
+        );
+    }
+    Ok(())
+}
+
+/// Delay between the purges of a reader still holding files
+const PURGE_DELAY: Duration = Duration::from_secs(2);
+
+/// Spool ids of the files in a `q rdr <zvm> all` listing
+fn reader_files(listing: &str) -> Vec<String> {
+    listing
+        .lines()
+        .skip(1)
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .filter(|cols| cols.len() > 6)
+        .map(|cols| cols[1].to_string())
+        .collect()
+}
+
+/// `<NAME>.<TYPE>` of the files in a `q rdr <zvm> all` listing
+fn reader_names(listing: &str) -> Vec<String> {
+    listing
+        .lines()
+        .skip(1)
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .filter(|cols| cols.len() > 6)
+        .map(|cols| match (cols.get(9), cols.get(10)) {
+            (Some(name), Some(kind)) => format!("{}.{}", name, kind),
+            _ => format!("{} (unnamed)", cols[1]),
+        })
+        .collect()
+}
+
+/// Checks that the reader of the guest holds exactly the punched files
+fn check_punched(cfg: &InstallConfig, targets: &[&str]) -> Result<()> {
+    let zvm = cfg.zvm.as_str();
+    let listing = query(cfg, &["q", "rdr", zvm, "all"])?;
+    if let Some(msg) = cp_error(&listing) {
+        warn!(
+            "Punched files not verified, the reader of '{}' can't be queried: {}",
+            zvm, msg
+        );
+        return Ok(());
+    }
+    let expected: Vec<String> = targets.iter().map(|t| t.to_ascii_uppercase()).collect();
+    ensure!(
+        reader_names(&listing) == expected,
+        "reader of '{}' doesn't hold the punched {}, it has:\n{}",
+        zvm,
+        expected.join(", "),
+        listing.trim_end()
+    );
+    debug!("Reader of '{}' holds {}", zvm, expected.join(", "));
+    Ok(())
+}
+
+/// Files left in the reader of the guest, None when it can't be queried
+fn remaining_files(cfg: &InstallConfig) -> Option<Vec<String>> {
+    let listing = query(cfg, &["q", "rdr", &cfg.zvm, "all"]).ok()?;
+    if cp_error(&listing).is_some() {
+        return None;
+    }
+    Some(reader_files(&listing))
+}
+
+/// Purges the reader of `zvm` and checks it is empty, up to
+/// --purge-retries more times while the guest still holds files
+fn clear(cfg: &InstallConfig) -> Result<()> {
+    let zvm = cfg.zvm.as_str();
+    // CP takes the virtual device number of our punch
+    let pun = cfg.vmur_devices.pun.trim_start_matches("0.0.");
+    match cfg.spool_class {
+        Some(class) => runcmd!(
+            cfg,
+            "vmcp",
+            "sp",
+            pun,
+            zvm,
+            "rdr",
+            "class",
+            class.to_string()
+        )?,
+        None => runcmd!(cfg, "vmcp", "sp", pun, zvm, "rdr")?,
+    }
+    let mut left = Vec::new();
+    for attempt in 0..=cfg.purge_retries {
+        if attempt > 0 {
+            let state = match left.len() {
+                0 => "not confirmed empty".to_string(),
+                n => format!("still has {} files", n),
+            };
+            warn!(
+                "Reader of '{}' {}, purging again in {:?} ({}/{})",
+                zvm, state, PURGE_DELAY, attempt, cfg.purge_retries
+            );
+            sleep(PURGE_DELAY);
+            if let Err(e) = runcmd!(cfg, "vmcp", "change", zvm, "rdr", "all", "nohold") {
+                warn!("Releasing held reader files of '{}': {:#}", zvm, e);
+            }
+        }
+        let purged = runcmd!(cfg, "vmcp", "pur", zvm, "rdr", "all");
+        if cfg.dry_run {
+            return Ok(());
+        }
+        match (purged, remaining_files(cfg)) {
+            (_, Some(files)) if files.is_empty() => return Ok(()),
+            (Ok(()), None) => return Ok(()),
+            (_, Some(files)) => left = files,
+            (Err(e), None) => {
+                debug!("Purging the reader of '{}': {:#}", zvm, e);
+                left.clear();
+            }
+        }
+    }
+    if let Some(blocked) = reader_blocked(cfg) {
+        return Err(anyhow::Error::new(blocked));
+    }
+    match left.is_empty() {
+        true => bail!(
+            "reader of '{}' not confirmed empty after {} retries, it can't be queried",
+            zvm,
+            cfg.purge_retries
+        ),
+        false => bail!(
+            "reader of '{}' not cleared after {} retries, files left: {}",
+            zvm,
+            cfg.purge_retries,
+            left.join(", ")
+        ),
+    }
+}
+
+/// Punches `file` as `target` in the record `format`, only the parm file
+/// can be converted, the kernel and initrd are always binary
+fn punch(cfg: &InstallConfig, target: &str, file: &Path, format: &PunchFormat) -> Result<()> {
+    let zvm = cfg.zvm.as_str();
+    let device = format!("/dev/vmpun-{}", cfg.vmur_devices.pun);
+    match format {
+        PunchFormat::Binary => {
+            runcmd_stream!(cfg, "vmur", "punch", "-d", &device, "-r", "-u", zvm, "-N", target, file)
+        }
+        PunchFormat::Text => runcmd_stream!(
+            cfg, "vmur", "punch", "-d", &device, "-r", "-t", "-u", zvm, "-N", target, file
+        ),
+        PunchFormat::Blocked => runcmd_stream!(
+            cfg,
+            "vmur",
+            "punch",
+            "-d",
+            &device,
+            "-r",
+            "-b",
+            "0x25,0x40",
+            "-u",
+            zvm,
+            "-N",
+            target,
+            file
+        ),
+    }
+}
+
+/// Parm file in the temporary directory, removed once punched unless
+/// --keep-parm
+struct ParmFile(PathBuf, bool);
+
+impl ParmFile {
+    fn path(zvm: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("zvmhelper-{}-{}.parm", zvm, std::process::id()))
+    }
+
+    fn write(cfg: &InstallConfig, cmdline: &str) -> Result<Self> {
+        let path = Self::path(&cfg.zvm);
+        std::fs::write(&path, cmdline)
+            .with_context(|| format!("writing '{}' to '{}'", cmdline, path.display()))?;
+        Ok(Self(path, cfg.keep_parm))
+    }
+}
+
+impl Drop for ParmFile {
+    fn drop(&mut self) {
+        if self.1 {
+            info!("Kept the parm file '{}'", self.0.display());
+        } else if let Err(e) = std::fs::remove_file(&self.0) {
+            warn!("Removing '{}': {}", self.0.display(), e);
+        }
+    }
 }
 
 fn send(cfg: &InstallConfig) -> Result<()> {
-    let url_to_path = |url: &Url| {
-        if url.scheme() == "file" {
-            match url.to_file_path() {
-                Ok(path) => path
-                    .to_str()
-                    .map(<_>::to_string)
-                    .ok_or(anyhow!("getting filename from '{}'", path.display())),
-                _ => Err(anyhow!("converting '{}'", url)),
+    let images = cfg.images.live()?;
+    let (kernel, initrd) = (local_path(&images.kernel)?, local_path(&images.initrd)?);
+
+    let initrd = punched_initrd(cfg, initrd);
+
+    let cmdline = parm_file(cfg, &generate_parm(cfg)?)?;
+    let parmfile = ParmFile::write(cfg, &cmdline)?;
+    if cfg.dry_run {
+        info!("Parm file '{}':\n{}", parmfile.0.display(), cmdline);
+    }
+
+    // text conversion or blocking would corrupt the IPL images
+    let files = [
+        (
+            cfg.kernel_name.as_str(),
+            kernel.as_path(),
+            &PunchFormat::Binary,
+        ),
+        (
+            cfg.parm_name.as_str(),
+            parmfile.0.as_path(),
+            &cfg.punch_format,
+        ),
+        (
+            cfg.initrd_name.as_str(),
+            initrd.as_path(),
+            &PunchFormat::Binary,
+        ),
+    ];
+    for (n, (target, file, format)) in files.iter().enumerate() {
+        let started = Instant::now();
+        let size = metadata(file).map(|m| m.len()).unwrap_or_default();
+        info!(
+            "Punching {} of {}: '{}' ({} bytes) to '{}': '{}'",
+            n + 1,
+            files.len(),
+            file.display(),
+            size,
+            cfg.zvm,
+            target
+        );
+        let punched = upload(cfg, file, &format!("{}.{}", cfg.zvm, target))?;
+        let result = punch(cfg, target, &punched, format);
+        if punched != *file {
+            if let Err(e) = runcmd!(cfg, "rm", "-f", &punched) {
+                warn!("Removing the copy of '{}': {:#}", file.display(), e);
             }
-        } else {
-            url.path()
-                .split('/')
-                .last()
-                .map(<_>::to_string)
-                .ok_or(anyhow!("getting filename from '{}'", url))
         }
-    };
+        result.map_err(|e| match reader_blocked(cfg) {
+            Some(blocked) => anyhow::Error::new(blocked).context(e),
+            None => e,
+        })?;
+        info!("Punched '{}' in {:.1?}", target, started.elapsed());
+    }
+    if cfg.dry_run {
+        return Ok(());
+    }
+    let targets: Vec<&str> = files.iter().map(|(target, _, _)| *target).collect();
+    check_punched(cfg, &targets)
+}
+
+/// Disk the installer writes to
+pub fn install_dev(cfg: &InstallConfig) -> Result<Option<String>> {
+    Ok(cfg.disk()?.map(|disk| disk.install_dev()))
+}
+
+/// Whether the installer fetches anything over the network
+fn needs_network(cfg: &InstallConfig, rootfs: &str) -> bool {
+    [cfg.ignition.as_str(), rootfs]
+        .iter()
+        .any(|url| !url.starts_with("file:") && url.contains("://"))
+}
+
+/// Keys given once per device or server, only exact repeats are dropped
+const REPEATABLE_KARGS: &[&str] = &[
+    "ip",
+    "nameserver",
+    "rd.znet",
+    "rd.zfcp",
+    "rd.dasd",
+    "console",
+];
+
+/// Kernel arguments in the order their keys first appear, a later value
+/// of a key replaces the earlier one in place
+#[derive(Debug, Default)]
+struct Kargs(Vec<String>);
 
-    let (kernel, initrd) = match &cfg.images {
-        Images::Artifacts(build) => {
-            let images = Live::from(build);
-            (url_to_path(&images.kernel), url_to_path(&images.initrd))
+impl Kargs {
+    fn key(karg: &str) -> &str {
+        karg.split('=').next().unwrap_or_default()
+    }
+
+    fn push(&mut self, karg: impl Into<String>) {
+        let karg = karg.into();
+        let key = Self::key(&karg);
+        let replaced = self
+            .0
+            .iter()
+            .position(|k| *k == karg || (Self::key(k) == key && !REPEATABLE_KARGS.contains(&key)));
+        match replaced {
+            Some(n) => {
+                if self.0[n] != karg {
+                    debug!("Karg {} replaces {}", karg, self.0[n]);
+                }
+                self.0[n] = karg;
+            }
+            None => self.0.push(karg),
         }
-        Images::LiveImages(images) => (url_to_path(&images.kernel), url_to_path(&images.initrd)),
-    };
+    }
 
-    let cmdline = parm(cfg);
-    let parmfile = "cmdline";
-    std::fs::write(parmfile, &cmdline)
-        .with_context(|| format!("writing '{}' to '{}'", cmdline, parmfile))?;
+    /// Adds the kargs of a command line
+    fn push_line(&mut self, line: &str) {
+        split_kargs(line)
+            .into_iter()
+            .for_each(|karg| self.push(karg));
+    }
+}
 
-    punch(&cfg.zvm, "coreos.kernel", &kernel?)?;
-    punch(&cfg.zvm, "coreos.parm", parmfile)?;
-    punch(&cfg.zvm, "coreos.initrd", &initrd?)
+/// Kargs of a command line, double quoted values may contain spaces
+fn split_kargs(line: &str) -> Vec<String> {
+    let mut kargs = Vec::new();
+    let mut karg = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                karg.push(c);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !karg.is_empty() {
+                    kargs.push(std::mem::take(&mut karg));
+                }
+            }
+            c => karg.push(c),
+        }
+    }
+    if !karg.is_empty() {
+        kargs.push(karg);
+    }
+    kargs
 }
 
-fn parm(cfg: &InstallConfig) -> String {
-    let mut s = String::new();
-    // network
-    s.push_str(&format!(
-        "rd.neednet=1 rd.znet={} ip={} {}",
-        cfg.znet,
-        cfg.ip,
-        cfg.dns
+impl std::fmt::Display for Kargs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.join(" "))
+    }
+}
+
+/// Most bytes of a parm file the kernel reads from the reader
+const PARM_LIMIT: usize = 895;
+/// Length of a punched record
+const RECORD_LEN: usize = 80;
+
+/// Parm file of `parm` in the --punch-format. Binary records are read as
+/// one line split every 80 bytes, text and blocked records are lines of
+/// their own, so the kargs are wrapped into lines of at most 80 bytes.
+/// The lines are not padded, but each is punched as a full record, so the
+/// 895 bytes the kernel reads are counted in full records. Fails when they
+/// are exceeded, naming the longest kargs to trim.
+pub fn parm_file(cfg: &InstallConfig, parm: &str) -> Result<String> {
+    let kargs = split_kargs(parm);
+    let (file, len) = match cfg.punch_format {
+        PunchFormat::Binary => (parm.to_string(), parm.len()),
+        PunchFormat::Text | PunchFormat::Blocked => {
+            let mut lines: Vec<String> = Vec::new();
+            for karg in &kargs {
+                ensure!(
+                    karg.len() <= RECORD_LEN,
+                    "karg '{}' is longer than a {} byte {:?} record, shorten it or use --punch-format binary",
+                    karg,
+                    RECORD_LEN,
+                    cfg.punch_format
+                );
+                match lines.last_mut() {
+                    Some(line) if line.len() + 1 + karg.len() <= RECORD_LEN => {
+                        line.push(' ');
+                        line.push_str(karg);
+                    }
+                    _ => lines.push(karg.clone()),
+                }
+            }
+            let len = lines.len() * RECORD_LEN;
+            (lines.join("\n"), len)
+        }
+    };
+    if len > PARM_LIMIT {
+        let mut longest: Vec<&String> = kargs.iter().collect();
+        longest.sort_by_key(|karg| std::cmp::Reverse(karg.len()));
+        let longest: Vec<String> = longest
             .iter()
-            .map(|ns| format!("nameserver={} ", ns))
-            .collect::<Vec<String>>()
-            .join(" ")
-    ));
+            .take(3)
+            .map(|karg| format!("{} ({} bytes)", karg, karg.len()))
+            .collect();
+        bail!(
+            "parm file is {} bytes as {:?} records, the kernel reads at most {}, trim the kargs, the longest are: {}",
+            len,
+            cfg.punch_format,
+            PARM_LIMIT,
+            longest.join(", ")
+        );
+    }
+    Ok(file)
+}
 
-    // target
-    if let Some(dasd) = &cfg.dasd {
-        s.push_str(&format!(
-            " rd.dasd={} coreos.inst.install_dev=/dev/disk/by-path/ccw-{}",
-            dasd, dasd
-        ));
-    } else if let Some(edev) = &cfg.edev {
-        s.push_str(&format!(
-            " rd.dasd={} coreos.inst.install_dev=/dev/disk/by-path/ccw-{}",
-            edev, edev
-        ));
-    } else if let Some(scsi) = &cfg.scsi {
-        s.push_str(&format!("rd.zfcp={} coreos.inst.install_dev=sda", scsi));
-    } else if let Some(mp) = &cfg.mp {
-        s.push_str(&format!(
-            " rd.multipath=default {} coreos.inst.install_dev=/dev/mapper/mpatha",
-            mp.iter()
-                .map(|s| format!("rd.zfcp={}", s))
-                .collect::<Vec<String>>()
-                .join(" "),
-        ));
-    }
-
-    let rootfs = match &cfg.images {
-        Images::Artifacts(b) => Live::from(b).rootfs.to_string(),
-        Images::LiveImages(i) => i.rootfs.to_string(),
+/// Generates the kernel parameters for the install
+pub fn generate_parm(cfg: &InstallConfig) -> Result<String> {
+    let rootfs = match &cfg.rootfs_url {
+        Some(url) => url.to_string(),
+        None => cfg.images.live()?.rootfs.to_string(),
     };
-    s.push_str(&format!(" coreos.inst=yes coreos.inst.insecure=yes coreos.inst.ignition_url={} coreos.live.rootfs_url={}",  
-        cfg.ignition, rootfs));
+
+    let mut kargs = Kargs::default();
+    // network
+    let neednet = match cfg.neednet {
+        NeedNet::Yes => true,
+        NeedNet::No => false,
+        NeedNet::Auto => needs_network(cfg, &rootfs),
+    };
+    if neednet {
+        kargs.push("rd.neednet=1");
+    }
+    for karg in cfg.network_kargs()? {
+        kargs.push(karg);
+    }
+    for ns in cfg.nameservers() {
+        kargs.push(match ns {
+            IpAddr::V6(v6) => format!("nameserver=[{}]", v6),
+            IpAddr::V4(v4) => format!("nameserver={}", v4),
+        });
+    }
+
+    // target
+    if let Some(disk) = cfg.disk()? {
+        disk.rd_kargs()
+            .into_iter()
+            .for_each(|karg| kargs.push(karg));
+        kargs.push(format!("coreos.inst.install_dev={}", disk.install_dev()));
+    }
+
+    kargs.push("coreos.inst=yes");
+    if cfg.insecure {
+        kargs.push("coreos.inst.insecure=yes");
+    }
+    if cfg.no_reboot {
+        kargs.push("coreos.inst.skip_reboot");
+    }
+    kargs.push(format!("coreos.inst.ignition_url={}", cfg.ignition));
+    kargs.push(format!("coreos.live.rootfs_url={}", rootfs));
+
+    // platform
+    if let Some(platform) = &cfg.platform_id {
+        kargs.push(format!("coreos.inst.platform_id={}", platform));
+    }
 
     // dfltcc
-    if let Some(dfltcc) = cfg.dfltcc {
-        s.push_str(&format!(" dfltcc={}", dfltcc));
+    if let Some(dfltcc) = &cfg.dfltcc {
+        kargs.push(format!("dfltcc={}", dfltcc));
+    }
+
+    // consoles
+    for console in &cfg.console {
+        kargs.push(format!("console={}", console));
+    }
+
+    // fips
+    if cfg.fips {
+        let explicit = cfg
+            .kargs()
+            .into_iter()
+            .map(str::to_string)
+            .chain(cfg.cmdline.iter().flat_map(|line| split_kargs(line)))
+            .find(|karg| Kargs::key(karg) == "fips" && karg != "fips=1");
+        if let Some(karg) = explicit {
+            bail!("--fips conflicts with {}", karg);
+        }
+        kargs.push("fips=1");
+    }
+
+    // extra kargs
+    for karg in cfg.kargs() {
+        kargs.push(karg);
     }
 
     // cmdline
     if let Some(cmdline) = &cfg.cmdline {
-        s.push_str(&format!(" dfltcc={}", cmdline));
+        kargs.push_line(cmdline);
+    }
+
+    Ok(kargs.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cmdline::Cmd;
+    use clap::Parser;
+
+    fn config(args: &[&str]) -> InstallConfig {
+        let base = [
+            "zvmhelper",
+            "install",
+            "-z",
+            "guest1",
+            "-i",
+            "http://srv/ign.json",
+        ];
+        let images = [
+            "live-images",
+            "--kernel",
+            "http://srv/kernel",
+            "--initrd",
+            "http://srv/initrd",
+            "--rootfs",
+            "http://srv/rootfs",
+        ];
+        let args = base.iter().chain(args).chain(&images);
+        Cmd::try_parse_from(args).unwrap().config()
+    }
+
+    #[test]
+    fn parm_subcommand() {
+        let args = [
+            "zvmhelper",
+            "parm",
+            "-z",
+            "guest1",
+            "-i",
+            "http://srv/ign.json",
+            "live-images",
+            "--kernel",
+            "http://srv/kernel",
+            "--initrd",
+            "http://srv/initrd",
+            "--rootfs",
+            "http://srv/rootfs",
+        ];
+        let cmd = Cmd::try_parse_from(args).unwrap();
+        assert!(matches!(cmd, Cmd::Parm(_)));
+        let parm = generate_parm(&cmd.config()).unwrap();
+        assert!(
+            parm.contains(" coreos.live.rootfs_url=http://srv/rootfs"),
+            "{}",
+            parm
+        );
+    }
+
+    #[test]
+    fn parm_golden() {
+        let cases = [
+            (
+                vec!["--dasd", "0.0.5c6e"],
+                include_str!("../tests/parm/dasd.parm"),
+            ),
+            (
+                vec!["--edev", "0.0.5c70", "--dfltcc", "true"],
+                include_str!("../tests/parm/edev.parm"),
+            ),
+            (
+                vec!["--dasd", "0.0.5c6e", "--rootfs-url", "http://cache/rootfs"],
+                include_str!("../tests/parm/rootfs-url.parm"),
+            ),
+            (
+                vec!["--scsi", "0.0.1900,0x500507630400d1e3,0x4001401100000000"],
+                include_str!("../tests/parm/scsi.parm"),
+            ),
+            (
+                vec![
+                    "--mp",
+                    "0.0.1900,0x500507630400d1e3,0x4001401100000000",
+                    "--mp",
+                    "0.0.1940,0x500507630410d1e3,0x4001401100000000",
+                    "--dns",
+                    "172.23.0.1",
+                    "--dns",
+                    "172.23.0.2",
+                ],
+                include_str!("../tests/parm/multipath.parm"),
+            ),
+        ];
+        for (args, golden) in cases {
+            assert_eq!(
+                generate_parm(&config(&args)).unwrap(),
+                golden.trim_end(),
+                "{:?}",
+                args
+            );
+        }
     }
 
-    s
+    #[test]
+    fn parm_single_spaced() {
+        let keys = [
+            "rd.neednet",
+            "rd.znet",
+            "ip",
+            "nameserver",
+            "rd.dasd",
+            "rd.zfcp",
+            "rd.multipath",
+            "coreos.inst.install_dev",
+            "coreos.inst",
+            "coreos.inst.insecure",
+            "coreos.inst.ignition_url",
+            "coreos.live.rootfs_url",
+        ];
+        for disk in [
+            vec!["--dasd", "0.0.5c6e"],
+            vec!["--edev", "0.0.5c70"],
+            vec!["--scsi", "0.0.1900,0x5005,0x4001"],
+            vec![
+                "--mp",
+                "0.0.1900,0x5005,0x4001",
+                "--mp",
+                "0.0.1940,0x5015,0x4001",
+            ],
+        ] {
+            let args: Vec<&str> = disk
+                .iter()
+                .copied()
+                .chain(["--dns", "172.23.0.1"])
+                .collect();
+            let parm = generate_parm(&config(&args)).unwrap();
+            assert_eq!(parm.trim(), parm);
+            for karg in parm.split(' ') {
+                let key = karg.split('=').next().unwrap();
+                assert!(keys.contains(&key), "'{}' in {}", karg, parm);
+            }
+        }
+    }
+
+    #[test]
+    fn parm_install_dev() {
+        let cases = [
+            (vec!["--dasd", "0.0.5c6e"], "/dev/disk/by-path/ccw-0.0.5c6e"),
+            (vec!["--edev", "0.0.5c70"], "/dev/disk/by-path/ccw-0.0.5c70"),
+            (vec!["--scsi", "0.0.1900,0x5005,0x4001"], "sda"),
+            (
+                vec![
+                    "--mp",
+                    "0.0.1900,0x5005,0x4001",
+                    "--mp",
+                    "0.0.1940,0x5015,0x4001",
+                ],
+                "/dev/mapper/mpatha",
+            ),
+            (
+                vec![
+                    "--target",
+                    "multipath",
+                    "--disk",
+                    "0.0.1900,0x5005,0x4001",
+                    "--disk",
+                    "0.0.1940,0x5015,0x4001",
+                    "--mp-name",
+                    "root",
+                ],
+                "/dev/mapper/root",
+            ),
+        ];
+        for (args, dev) in cases {
+            let cfg = config(&args);
+            let disk = cfg.disk().unwrap().unwrap();
+            assert_eq!(disk.install_dev(), dev, "{:?}", args);
+            let parm = generate_parm(&cfg).unwrap();
+            let karg = format!(" coreos.inst.install_dev={} ", dev);
+            assert!(parm.contains(&karg), "{}", parm);
+        }
+        let parm = generate_parm(&config(&[])).unwrap();
+        assert!(!parm.contains("coreos.inst.install_dev="), "{}", parm);
+    }
+
+    #[test]
+    fn parm_ipv6() {
+        let parm = generate_parm(&config(&[
+            "--ip",
+            "[2001:db8::2]::[2001:db8::1]:64:guest:enc600:none",
+            "--dns",
+            "2001:db8::53,10.0.0.53",
+        ]))
+        .unwrap();
+        assert!(
+            parm.contains(
+                " ip=[2001:db8::2]::[2001:db8::1]:64:guest:encbdf0:none nameserver=[2001:db8::53] nameserver=10.0.0.53 "
+            ),
+            "{}",
+            parm
+        );
+    }
+
+    #[test]
+    fn parm_no_nameserver() {
+        let parm = generate_parm(&config(&["--dns", ""])).unwrap();
+        assert!(!parm.contains("nameserver"), "{}", parm);
+        assert!(generate_parm(&config(&[]))
+            .unwrap()
+            .contains(" nameserver=172.23.0.1 "));
+    }
+
+    #[test]
+    fn parm_nameservers_spacing() {
+        let parm = generate_parm(&config(&[
+            "--dns",
+            "10.0.0.53,10.0.0.54,fd00::53",
+            "--scsi",
+            "0.0.1900,0x5005,0x4001",
+        ]))
+        .unwrap();
+        assert!(
+            parm.contains(
+                " nameserver=10.0.0.53 nameserver=10.0.0.54 nameserver=[fd00::53] rd.zfcp="
+            ),
+            "{}",
+            parm
+        );
+        assert!(!parm.contains("  "), "{:?}", parm);
+        assert_eq!(parm.trim(), parm);
+    }
+
+    #[test]
+    fn parm_two_nics() {
+        let parm = generate_parm(&config(&[
+            "--znet",
+            "qeth,0.0.bdf0,0.0.bdf1,0.0.bdf2,layer2=1",
+            "--ip",
+            "172.23.237.227::172.23.0.1:255.255.0.0:coreos:encbdf0:none",
+            "--znet",
+            "qeth,0.0.0600,0.0.0601,0.0.0602",
+            "--ip",
+            "10.0.0.2:::255.255.255.0::enc600:none",
+            "--dns",
+            "172.23.0.1",
+        ]))
+        .unwrap();
+        assert!(
+            parm.starts_with(
+                "rd.neednet=1 \
+rd.znet=qeth,0.0.bdf0,0.0.bdf1,0.0.bdf2,layer2=1 ip=172.23.237.227::172.23.0.1:255.255.0.0:coreos:encbdf0:none \
+rd.znet=qeth,0.0.0600,0.0.0601,0.0.0602 ip=10.0.0.2:::255.255.255.0::enc600:none \
+nameserver=172.23.0.1 "
+            ),
+            "{}",
+            parm
+        );
+        let err = generate_parm(&config(&[
+            "--znet",
+            "qeth,0.0.0600",
+            "--znet",
+            "qeth,0.0.0700",
+        ]));
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "1 --ip for 2 --znet, each interface needs both"
+        );
+    }
+
+    #[test]
+    fn parm_vlan() {
+        let parm = generate_parm(&config(&["--vlan", "100", "--dns", "172.23.0.1"])).unwrap();
+        assert!(
+            parm.contains(
+                ":coreos:encbdf0.100:none vlan=encbdf0.100:encbdf0 nameserver=172.23.0.1 "
+            ),
+            "{}",
+            parm
+        );
+    }
+
+    #[test]
+    fn parm_insecure() {
+        let parm = generate_parm(&config(&[])).unwrap();
+        assert!(parm.contains(" coreos.inst.insecure=yes "), "{}", parm);
+        for arg in ["--insecure=yes", "--insecure=true"] {
+            assert!(generate_parm(&config(&[arg]))
+                .unwrap()
+                .contains("coreos.inst.insecure=yes"));
+        }
+        for arg in ["--insecure=no", "--insecure=false"] {
+            let parm = generate_parm(&config(&[arg])).unwrap();
+            assert!(!parm.contains("coreos.inst.insecure"), "{}", parm);
+        }
+    }
+
+    #[test]
+    fn parm_no_reboot() {
+        let parm = generate_parm(&config(&[])).unwrap();
+        assert!(!parm.contains("skip_reboot"), "{}", parm);
+        let parm = generate_parm(&config(&["--no-reboot"])).unwrap();
+        assert!(
+            parm.contains(" coreos.inst.insecure=yes coreos.inst.skip_reboot "),
+            "{}",
+            parm
+        );
+        let args = [
+            "zvmhelper",
+            "install",
+            "--no-reboot",
+            "--auto-ipl",
+            "artifacts",
+        ];
+        assert!(Cmd::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn parm_consoles() {
+        let parm = generate_parm(&config(&["--console=ttyS0,115200", "--console"])).unwrap();
+        assert!(
+            parm.ends_with(" console=ttyS0,115200 console=ttysclp0"),
+            "{}",
+            parm
+        );
+        let parm = generate_parm(&config(&[
+            "--console=ttysclp0",
+            "--console=hvc0",
+            "--cmdline",
+            "console=ttysclp0 console=tty1",
+        ]))
+        .unwrap();
+        assert!(
+            parm.ends_with(" console=ttysclp0 console=hvc0 console=tty1"),
+            "{}",
+            parm
+        );
+        assert!(!generate_parm(&config(&[])).unwrap().contains("console="));
+    }
+
+    #[test]
+    fn parm_fips() {
+        let parm = generate_parm(&config(&["--fips"])).unwrap();
+        assert!(parm.ends_with(" fips=1"), "{}", parm);
+        let parm = generate_parm(&config(&["--fips", "--karg", "fips=1"])).unwrap();
+        assert_eq!(parm.matches("fips=").count(), 1, "{}", parm);
+        assert!(!generate_parm(&config(&[])).unwrap().contains("fips"));
+
+        let err = generate_parm(&config(&["--fips", "--karg", "fips=0"])).unwrap_err();
+        assert_eq!(err.to_string(), "--fips conflicts with fips=0");
+        assert!(generate_parm(&config(&["--fips", "--cmdline", "quiet fips=0"])).is_err());
+    }
+
+    #[test]
+    fn parm_mtu() {
+        let parm = generate_parm(&config(&["--mtu", "9000", "--dns", "172.23.0.1"])).unwrap();
+        assert!(
+            parm.contains(":coreos:encbdf0:none:9000 nameserver=172.23.0.1 "),
+            "{}",
+            parm
+        );
+    }
+
+    #[test]
+    fn parm_bond() {
+        let parm = generate_parm(&config(&[
+            "--znet",
+            "qeth,0.0.bdf0,0.0.bdf1,0.0.bdf2,layer2=1",
+            "--znet",
+            "qeth,0.0.0600,0.0.0601,0.0.0602,layer2=1",
+            "--bond",
+            "bond0:encbdf0,enc600:mode=active-backup,miimon=100",
+            "--dns",
+            "172.23.0.1",
+        ]))
+        .unwrap();
+        assert!(
+            parm.contains(
+                "rd.znet=qeth,0.0.bdf0,0.0.bdf1,0.0.bdf2,layer2=1 \
+                 rd.znet=qeth,0.0.0600,0.0.0601,0.0.0602,layer2=1 \
+                 ip=172.23.237.227::172.23.0.1:255.255.0.0:coreos:bond0:none \
+                 bond=bond0:encbdf0,enc600:mode=active-backup,miimon=100 \
+                 nameserver=172.23.0.1 "
+            ),
+            "{}",
+            parm
+        );
+    }
+
+    #[test]
+    fn parm_neednet() {
+        let parm = generate_parm(&config(&[])).unwrap();
+        assert!(parm.starts_with("rd.neednet=1 rd.znet="), "{}", parm);
+        let parm = generate_parm(&config(&["--neednet", "no"])).unwrap();
+        assert!(parm.starts_with("rd.znet="), "{}", parm);
+
+        let local = ["-i", "file:///srv/ign.json"];
+        let args = ["zvmhelper", "install", "-z", "guest1"];
+        let images = [
+            "live-images",
+            "--kernel",
+            "k",
+            "--initrd",
+            "i",
+            "--rootfs",
+            "r",
+        ];
+        let cfg = Cmd::try_parse_from(args.iter().chain(&local).chain(&images))
+            .unwrap()
+            .config();
+        assert!(!generate_parm(&cfg).unwrap().contains("rd.neednet"));
+        let cfg = Cmd::try_parse_from(
+            args.iter()
+                .chain(&local)
+                .chain(&["--neednet", "yes"])
+                .chain(&images),
+        )
+        .unwrap()
+        .config();
+        assert!(generate_parm(&cfg).unwrap().starts_with("rd.neednet=1 "));
+    }
+
+    #[test]
+    fn reader_listing() {
+        let listing = "\
+ORIGINID FILE CLASS RECORDS  CPY HOLD DATE  TIME     NAME      TYPE      DIST
+HELPER   0021 A PUN 00000100 001 NONE 03/14 10:19:30 COREOS    KERNEL    GUEST1
+OPERATOR 0023 A PUN 00000009 001 SYS  03/14 10:19:32 OLD       FILE      GUEST1
+";
+        assert_eq!(reader_files(listing), ["0021", "0023"]);
+        assert!(reader_files("NO RDR FILES\n").is_empty());
+    }
+
+    #[test]
+    fn reader_file_names() {
+        let listing = "\
+ORIGINID FILE CLASS RECORDS  CPY HOLD DATE  TIME     NAME      TYPE      DIST
+HELPER   0021 A PUN 00000100 001 NONE 03/14 10:19:30 COREOS    KERNEL    GUEST1
+HELPER   0022 A PUN 00000001 001 NONE 03/14 10:19:31 COREOS    PARM      GUEST1
+HELPER   0024 A PUN 00000009 001 NONE 03/14 10:19:32
+";
+        assert_eq!(
+            reader_names(listing),
+            ["COREOS.KERNEL", "COREOS.PARM", "0024 (unnamed)"]
+        );
+        assert!(reader_names("NO RDR FILES\n").is_empty());
+    }
+
+    #[test]
+    fn reader_held_files() {
+        let listing = "\
+ORIGINID FILE CLASS RECORDS  CPY HOLD DATE  TIME     NAME      TYPE      DIST
+HELPER   0021 A PUN 00000100 001 NONE 03/14 10:19:30 COREOS    KERNEL    GUEST1
+HELPER   0022 A PUN 00000001 001 USER 03/14 10:19:31 COREOS    PARM      GUEST1
+OPERATOR 0023 A PUN 00000009 001 SYS  03/14 10:19:32 OLD       FILE      GUEST1
+";
+        let files = held_files(listing);
+        assert_eq!(files, ["0022 (USER)", "0023 (SYS)"]);
+        let blocked = ReaderBlocked {
+            zvm: "guest1".into(),
+            files,
+        };
+        assert_eq!(
+            blocked.to_string(),
+            "reader of 'guest1' needs manual intervention, these files are held: 0022 (USER), 0023 (SYS)"
+        );
+    }
+
+    #[test]
+    fn parm_dfltcc() {
+        let parm = generate_parm(&config(&["--dfltcc", "off"])).unwrap();
+        assert!(parm.ends_with(" dfltcc=off"), "{}", parm);
+        let parm = generate_parm(&config(&["--dfltcc", "inf_only", "-c", "quiet"])).unwrap();
+        assert!(parm.ends_with(" dfltcc=inf_only quiet"), "{}", parm);
+        let parm = generate_parm(&config(&["--dfltcc", "true"])).unwrap();
+        assert!(parm.ends_with(" dfltcc=on"), "{}", parm);
+    }
+
+    #[test]
+    fn parm_extra_kargs() {
+        let parm = generate_parm(&config(&[
+            "--karg",
+            "console=ttysclp0",
+            "--karg",
+            "quiet",
+            "--karg",
+            "console=ttyS0",
+            "-c",
+            "rd.debug",
+        ]))
+        .unwrap();
+        assert!(parm.ends_with(" quiet console=ttyS0 rd.debug"), "{}", parm);
+        assert!(!parm.contains("ttysclp0"), "{}", parm);
+
+        let args = ["zvmhelper", "install", "-i", "http://ign", "--karg"];
+        for karg in ["", "=x", "a b"] {
+            let cmd = Cmd::try_parse_from(args.iter().chain(&[karg, "artifacts"]));
+            assert!(cmd.is_err(), "{:?}", karg);
+        }
+    }
+
+    #[test]
+    fn parm_dedup() {
+        let parm = generate_parm(&config(&[
+            "--dfltcc",
+            "on",
+            "--dns",
+            "172.23.0.1",
+            "-c",
+            "quiet dfltcc=off nameserver=172.23.0.1 nameserver=172.23.0.2 quiet",
+        ]))
+        .unwrap();
+        assert!(
+            parm.contains(" nameserver=172.23.0.1 coreos.inst=yes "),
+            "{}",
+            parm
+        );
+        assert!(
+            parm.ends_with(" dfltcc=off quiet nameserver=172.23.0.2"),
+            "{}",
+            parm
+        );
+        assert_eq!(parm.matches("dfltcc=").count(), 1, "{}", parm);
+
+        let mut kargs = Kargs::default();
+        kargs.push_line(
+            r#"  rd.debug console=ttysclp0  foo="a b=c"  console=ttyS0 foo=bar rd.debug"#,
+        );
+        assert_eq!(
+            kargs.to_string(),
+            "rd.debug console=ttysclp0 foo=bar console=ttyS0"
+        );
+    }
+
+    #[test]
+    fn parm_records() {
+        let cfg = config(&["--dasd", "0.0.5c6e", "--punch-format", "text"]);
+        let parm = generate_parm(&cfg).unwrap();
+        let file = parm_file(&cfg, &parm).unwrap();
+        assert!(file.lines().all(|line| line.len() <= 80), "{}", file);
+        assert_eq!(split_kargs(&file), split_kargs(&parm));
+        assert_eq!(parm_file(&config(&[]), &parm).unwrap(), parm);
+
+        let long = format!("-c=rd.lvm.lv={}", "x".repeat(80));
+        let cfg = config(&["--punch-format", "blocked", &long]);
+        let err = parm_file(&cfg, &generate_parm(&cfg).unwrap()).unwrap_err();
+        assert!(
+            err.to_string().ends_with(
+                "is longer than a 80 byte Blocked record, shorten it or use --punch-format binary"
+            ),
+            "{}",
+            err
+        );
+
+        let long = format!("-c=rd.lvm.lv={}", "x".repeat(700));
+        let cfg = config(&[&long]);
+        let err = parm_file(&cfg, &generate_parm(&cfg).unwrap()).unwrap_err();
+        assert!(
+            err.to_string().contains(
+                "as Binary records, the kernel reads at most 895, trim the kargs, the longest are: rd.lvm.lv=xxx"
+            ),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn ssh_commands() {
+        assert_eq!(shell_quote(OsStr::new("0.0.000d")), "0.0.000d");
+        assert_eq!(shell_quote(OsStr::new("")), "''");
+        assert_eq!(shell_quote(OsStr::new("q rdr all")), "'q rdr all'");
+        assert_eq!(shell_quote(OsStr::new("it's")), "'it'\\''s'");
+
+        let mut cmd = Command::new("vmcp");
+        cmd.args(["send cp guest1 ipl 000c"]);
+        let ssh = ssh_command("admin@bastion", &cmd);
+        assert_eq!(ssh.get_program(), "ssh");
+        assert_eq!(
+            ssh.get_args().collect::<Vec<_>>(),
+            [
+                "-o",
+                "BatchMode=yes",
+                "admin@bastion",
+                "--",
+                "vmcp 'send cp guest1 ipl 000c'"
+            ]
+        );
+
+        let local = remote(&config(&[]), Command::new("vmcp"));
+        assert_eq!(local.get_program(), "vmcp");
+        let ssh = remote(&config(&["--ssh", "admin@bastion"]), Command::new("vmcp"));
+        assert_eq!(ssh.get_program(), "ssh");
+    }
+
+    #[test]
+    fn required_tools() {
+        let dir = std::env::temp_dir().join(format!("zvmhelper-tools-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for tool in ["modprobe", "chccwdev"] {
+            std::fs::write(dir.join(tool), "").unwrap();
+        }
+        let missing = missing_tools(dir.as_os_str(), TOOLS);
+        assert_eq!(
+            missing.iter().map(|(tool, _)| *tool).collect::<Vec<_>>(),
+            ["cio_ignore", "vmcp", "vmur"]
+        );
+        assert_eq!(missing_tools(OsStr::new(""), TOOLS).len(), TOOLS.len());
+        assert_eq!(
+            describe_missing(&missing),
+            "cio_ignore, vmcp, vmur (from s390utils-base or s390-tools)"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+        check_tools(&config(&["--dry-run"])).unwrap();
+    }
+
+    #[test]
+    fn device_states() {
+        assert!(online_state("1\n"));
+        assert!(!online_state("0\n"));
+        assert!(!online_state(""));
+        assert!(ignored_state("Device 0.0.000c is ignored.\n"));
+        assert!(!ignored_state("Device 0.0.000c is not ignored.\n"));
+        assert!(!ignored_state(""));
+    }
+
+    #[test]
+    fn dry_run_punch() {
+        let cfg = config(&["--dasd", "0.0.5c6e", "--dry-run", "-q"]);
+        ipl_zvm_guest(&cfg).unwrap();
+        assert!(ONLINED.lock().unwrap().is_empty());
+        let parmfile = ParmFile::path(&cfg.zvm);
+        assert!(!parmfile.exists());
+
+        let cfg = config(&["--dasd", "0.0.5c6e", "--dry-run", "-q", "--keep-parm"]);
+        ipl_zvm_guest(&cfg).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&parmfile).unwrap(),
+            generate_parm(&cfg).unwrap()
+        );
+        std::fs::remove_file(parmfile).unwrap();
+    }
+
+    #[test]
+    fn failed_command_output() {
+        let mut cmd = Command::new("sh");
+        cmd.args([
+            "-c",
+            "echo HCP003E Invalid option; echo non-zero CP response >&2; exit 1",
+        ]);
+        let err = wait(cmd, true).unwrap_err().to_string();
+        assert!(
+            err.ends_with(
+                "failed with exit status: 1: non-zero CP response; HCP003E Invalid option"
+            ),
+            "{}",
+            err
+        );
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "exit 2"]);
+        let err = wait(cmd, true).unwrap_err().to_string();
+        assert!(err.ends_with("failed with exit status: 2"), "{}", err);
+        wait(Command::new("true"), true).unwrap();
+    }
+
+    #[test]
+    fn guest_logon() {
+        logged_on("GUEST1", "GUEST1   - DSC\n").unwrap();
+        let err = logged_on("GUEST1", "HCPCQU045E GUEST1 not logged on\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "guest 'GUEST1' is not logged on, XAUTOLOG it first: 'vmcp xautolog GUEST1' (HCPCQU045E GUEST1 not logged on)"
+        );
+        let err = logged_on("NOBODY", "HCPCQU003E Invalid option - NOBODY").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "guest 'NOBODY' does not exist (HCPCQU003E Invalid option - NOBODY)"
+        );
+        assert_eq!(cp_error("NO RDR FILES"), None);
+        assert_eq!(
+            cp_error("HCPCQF002E Invalid operand - RDR"),
+            Some("HCPCQF002E Invalid operand - RDR")
+        );
+    }
 }
@@ -0,0 +1,228 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Installation of Fedora CoreOS and RHCOS to IBM zVM guests: resolving
+//! the live images, generating the parm and punching the files to the
+//! guest's reader. The `zvmhelper` binary is a thin wrapper around
+//! [`run`].
+
+pub mod cmdline;
+pub mod config;
+pub mod configfile;
+mod console;
+pub mod error;
+mod fleet;
+pub mod ignition;
+pub mod images;
+pub mod ipl;
+mod logger;
+mod network;
+mod oci;
+mod progress;
+mod serve;
+pub mod stream;
+mod summary;
+mod verify;
+mod watchdog;
+
+pub use crate::cmdline::{Build, Cmd, Images, InstallConfig, Live};
+pub use crate::images::download_images;
+pub use crate::ipl::{generate_parm, ipl_zvm_guest};
+
+use crate::summary::Summary;
+use anyhow::Result;
+
+fn install(c: &InstallConfig, summary: &mut Summary) -> Result<()> {
+    summary.enter("validate");
+    c.validate()?;
+    if !c.ignition_file.is_empty() || c.ignition_additions() || c.ignition_path().is_some() {
+        summary.enter("ignition");
+        ignition::write_merged(c)?;
+    }
+    if !c.no_validate_ignition && !c.check_ignition {
+        summary.enter("ignition");
+        ignition::validate(c)?;
+    }
+    summary.enter("setup");
+    ipl::check_tools(c)?;
+    ipl::install_cleanup_handler(c)?;
+    if c.preflight {
+        summary.enter("preflight");
+        images::preflight(c)?;
+    }
+    if c.check_network {
+        summary.enter("network");
+        network::check(c)?;
+    }
+    if c.check_ignition {
+        summary.enter("ignition");
+        ignition::check(c)?;
+    }
+    summary.enter("download");
+    let staged = images::download_images(c)?;
+    if let Some(path) = &c.manifest_out {
+        images::write_manifest(path, &staged)?;
+    }
+    summary.staged(staged);
+    summary.enter("punch");
+    ipl::ipl_zvm_guest(c)?;
+    if c.dry_run {
+        return Ok(());
+    }
+    if c.cleanup_downloads {
+        summary.enter("cleanup");
+        images::cleanup_downloads(c)?;
+    }
+    if let Some(path) = &c.console_log {
+        summary.enter("console");
+        console::capture(c, path)?;
+    } else if c.auto_ipl {
+        summary.enter("ipl");
+        console::ipl(c)?;
+    }
+    let step = match (c.auto_ipl, &c.console_log) {
+        (true, Some(_)) => format!("'{}' is installed", c.zvm),
+        (true, None) => format!("'{}' is IPLed and installing", c.zvm),
+        (false, _) => "Please login to zVM and IPL and manually: '#cp ipl c'".to_string(),
+    };
+    summary.next_steps.push(step);
+    if c.no_reboot {
+        summary.next_steps.push(format!(
+            "'{}' stays in the live system once installed, IPL it manually from its install disk",
+            c.zvm
+        ));
+    }
+    Ok(())
+}
+
+/// An `install` once its images are resolved: serves the Ignition config
+/// if needed, then installs the fleet or the guest
+fn install_command(mut c: InstallConfig) -> Result<()> {
+    let server = match &c.serve_addr() {
+        Some(addr) => Some(serve::Server::start(&c, addr)?),
+        None => None,
+    };
+    if let Some(server) = &server {
+        c.ignition_source = Some(std::mem::replace(&mut c.ignition, server.url.clone()));
+    }
+    log::info!("{}", c);
+    if let Some(manifest) = &c.fleet {
+        watchdog::set_stage("fleet");
+        ipl::install_cleanup_handler(&c)?;
+        return fleet::install(&c, manifest);
+    }
+    let mut summary = Summary::new(&c);
+    let mut result = install(&c, &mut summary);
+    if let (Some(server), Ok(()), false) = (&server, &result, c.dry_run) {
+        summary.enter("serve");
+        if c.auto_ipl {
+            log::info!("Serving the Ignition config at {}", server.url);
+        } else if !c.quiet {
+            println!(
+                "Serving the Ignition config at {}, IPL '{}' now",
+                server.url, c.zvm
+            );
+        }
+        let timeout = std::time::Duration::from_secs(c.ignition_serve_timeout);
+        if !server.wait(timeout) {
+            result = Err(anyhow::anyhow!(
+                "'{}' did not fetch its Ignition config within {}s",
+                c.zvm,
+                c.ignition_serve_timeout
+            ));
+        }
+    }
+    if let Err(e) = &result {
+        summary.failed(e);
+    }
+    summary.print(&c);
+    result
+}
+
+/// Parm of the `parm` subcommand, with the URL the install serves the
+/// Ignition config at in place of --ignition
+fn parm(mut c: InstallConfig) -> Result<String> {
+    if let Some(addr) = c.serve_addr() {
+        let url = serve::served_url(&c, &addr)?;
+        if addr.port == 0 {
+            log::warn!(
+                "The install serves the Ignition config on an ephemeral port, <port> stands for it"
+            );
+        }
+        c.ignition_source = Some(std::mem::replace(&mut c.ignition, url));
+    }
+    ipl::generate_parm(&c)
+}
+
+/// Runs a parsed command line as the `zvmhelper` binary does, an
+/// `install` punches and IPLs the guest
+pub fn run(cmd: Cmd) -> Result<()> {
+    if let Some(options) = cmd.options() {
+        logger::init(options);
+        if let Some(dir) = &options.cache_dir {
+            images::set_cache_dir(dir)?;
+        }
+    }
+    match cmd {
+        Cmd::Install(mut c) => {
+            stream::resolve(&mut c)?;
+            let config = c.clone();
+            watchdog::run(&config, move || install_command(c))
+        }
+        Cmd::Verify(mut c) => {
+            stream::resolve(&mut c)?;
+            verify::verify(&c)
+        }
+        Cmd::Parm(mut c) => {
+            stream::resolve(&mut c)?;
+            println!("{}", parm(c)?);
+            Ok(())
+        }
+        Cmd::Completions { shell } => {
+            Cmd::completions(shell, &mut std::io::stdout());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn served_parm() {
+        let parse = |extra: &[&str]| {
+            let args = ["zvmhelper", "parm", "-z", "guest1", "-i", "/tmp/ign.json"];
+            match Cmd::try_parse_from(args.iter().chain(extra).chain(&["artifacts"])).unwrap() {
+                Cmd::Parm(c) => c,
+                _ => unreachable!(),
+            }
+        };
+        let rendered = parm(parse(&["--ignition-serve", "192.0.2.5:8080"])).unwrap();
+        assert!(
+            rendered
+                .contains(" coreos.inst.ignition_url=http://192.0.2.5:8080/ignition.guest1.ign "),
+            "{}",
+            rendered
+        );
+        let rendered = parm(parse(&["--ip", "127.0.0.10::127.0.0.1:8"])).unwrap();
+        assert!(
+            rendered.contains("=http://127.0.0.1:<port>/ignition.guest1.ign "),
+            "{}",
+            rendered
+        );
+        assert!(!rendered.contains("/tmp/ign.json"), "{}", rendered);
+        // no static --ip to route to
+        assert!(parm(parse(&["--dhcp"])).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::cmdline::{InstallConfig, OutputFormat};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Info messages are the progress output on stdout, everything else goes
+/// to stderr
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Info => println!("{}", record.args()),
+            Level::Warn => eprintln!("Warning: {}", record.args()),
+            Level::Error => eprintln!("Error: {}", record.args()),
+            level => eprintln!("[{}] {}", level, record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Errors only with --quiet, warnings with JSON output, else progress
+/// and more with each -v
+fn level(cfg: &InstallConfig) -> LevelFilter {
+    if cfg.quiet {
+        LevelFilter::Error
+    } else if cfg.output == OutputFormat::Json {
+        LevelFilter::Warn
+    } else {
+        match cfg.verbose {
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    }
+}
+
+/// Installs the logger at the verbosity of `cfg`
+pub fn init(cfg: &InstallConfig) {
+    if log::set_logger(&Logger).is_ok() {
+        log::set_max_level(level(cfg));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cmdline::test::install_with;
+
+    #[test]
+    fn verbosity() {
+        let level =
+            |flags: &[&str]| level(&install_with("http://srv/ign.json", flags, &[]).unwrap());
+        assert_eq!(level(&[]), LevelFilter::Info);
+        assert_eq!(level(&["-v"]), LevelFilter::Debug);
+        assert_eq!(level(&["-vv"]), LevelFilter::Trace);
+        assert_eq!(level(&["-v", "--verbose", "-v"]), LevelFilter::Trace);
+        assert_eq!(level(&["--output", "json"]), LevelFilter::Warn);
+        assert_eq!(level(&["-q"]), LevelFilter::Error);
+    }
+}
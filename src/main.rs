@@ -10,20 +10,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-mod cmdline;
-mod images;
-mod ipl;
-
-use crate::cmdline::*;
-use anyhow::Result;
 use clap::Parser;
+use zvm_helper::{configfile, error, Cmd};
 
-fn main() -> Result<()> {
-    match Cmd::parse() {
-        Cmd::Install(c) => {
-            println!("{}", c);
-            images::download_images(&c)?;
-            ipl::ipl_zvm_guest(&c)
-        }
+fn main() {
+    let args = std::env::args_os().collect();
+    let result = configfile::expand(args).and_then(|args| zvm_helper::run(Cmd::parse_from(args)));
+    if let Err(e) = result {
+        eprintln!("Error: {:?}", e);
+        std::process::exit(error::exit_code(&e));
     }
 }
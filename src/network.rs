@@ -0,0 +1,136 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::cmdline::InstallConfig;
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use reqwest::Url;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::Command;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Hosts the guest fetches its Ignition config and rootfs from
+fn fetched_hosts(cfg: &InstallConfig) -> Vec<Url> {
+    let rootfs = match &cfg.rootfs_url {
+        Some(url) => Some(url.clone()),
+        // a build without images fails on its own
+        None => cfg.images.live().ok().map(|live| live.rootfs),
+    };
+    vec![Url::parse(&cfg.ignition).ok(), rootfs]
+        .into_iter()
+        .flatten()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .collect()
+}
+
+/// Whether a TCP connection to the host of `url` can be opened
+fn reachable(url: &Url) -> Result<()> {
+    let host = url.host_str().context("URL without host")?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<_> = (host, port)
+        .to_socket_addrs()
+        .with_context(|| format!("resolving '{}'", host))?
+        .collect();
+    for addr in &addrs {
+        if TcpStream::connect_timeout(addr, TIMEOUT).is_ok() {
+            return Ok(());
+        }
+    }
+    bail!("no connection to {}:{}", host, port)
+}
+
+/// Pings the gateway of a static --ip, failing when it doesn't answer, and
+/// warns about Ignition and rootfs hosts that can't be connected to. The
+/// checks run from this host, which must be on the guest's network, they
+/// are skipped with --ssh as only the remote host is. Returns what was
+/// checked.
+pub fn check(cfg: &InstallConfig) -> Result<&'static str> {
+    if let Some(host) = &cfg.ssh {
+        info!(
+            "Not checking the network, the guest's network is the one of --ssh {}",
+            host
+        );
+        return Ok("skipped with --ssh");
+    }
+    match cfg.static_ip().and_then(|net| net.gw) {
+        Some(gw) => {
+            let status = Command::new("ping")
+                .args(["-c", "1", "-W", &TIMEOUT.as_secs().to_string()])
+                .arg(gw.to_string())
+                .output()
+                .context("running ping")?
+                .status;
+            if !status.success() {
+                bail!("gateway {} of --ip does not answer to ping", gw);
+            }
+        }
+        None => warn!("No static gateway in --ip, not checked"),
+    }
+    for url in fetched_hosts(cfg) {
+        if let Err(e) = reachable(&url) {
+            warn!("the guest may not reach {}: {:#}", url, e);
+        }
+    }
+    Ok("gateway answers")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cmdline::Cmd;
+    use clap::Parser;
+
+    #[test]
+    fn checked_hosts() {
+        let args = [
+            "zvmhelper",
+            "install",
+            "-i",
+            "http://ign:8080/config.ign",
+            "--rootfs-url",
+            "https://cache/rootfs.img",
+            "live-images",
+            "--kernel",
+            "/srv/kernel",
+            "--initrd",
+            "/srv/initrd",
+            "--rootfs",
+            "/srv/rootfs",
+        ];
+        let cfg = Cmd::try_parse_from(args).unwrap().config();
+        let hosts: Vec<String> = fetched_hosts(&cfg).iter().map(Url::to_string).collect();
+        assert_eq!(
+            hosts,
+            ["http://ign:8080/config.ign", "https://cache/rootfs.img"]
+        );
+    }
+
+    #[test]
+    fn remote_skipped() {
+        let args = [
+            "zvmhelper",
+            "install",
+            "-i",
+            "http://ign.invalid/config.ign",
+            "--ip",
+            "192.0.2.10::192.0.2.1:24",
+            "--ssh",
+            "admin@bastion",
+            "artifacts",
+        ];
+        let cfg = Cmd::try_parse_from(args).unwrap().config();
+        // the gateway isn't pinged from here
+        assert_eq!(check(&cfg).unwrap(), "skipped with --ssh");
+    }
+}
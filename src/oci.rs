@@ -0,0 +1,231 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::cmdline::Oci;
+use crate::images::local_path;
+use anyhow::{anyhow, bail, Context, Result};
+use log::{info, warn};
+use serde_json::Value;
+use std::fs::{read, remove_dir_all, File};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Live image names in the layers, as `<prefix>-<suffix>`
+const LIVE_SUFFIXES: [&str; 3] = [
+    "live-kernel-s390x",
+    "live-initramfs.s390x.img",
+    "live-rootfs.s390x.img",
+];
+
+fn output(cmd: &mut Command) -> Result<String> {
+    let out = cmd.output().with_context(|| format!("running {:?}", cmd))?;
+    if !out.status.success() {
+        bail!(
+            "{:?} failed with {}: {}",
+            cmd,
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// Path of a blob of the OCI layout in `dir`
+fn blob(dir: &Path, digest: &str) -> Result<PathBuf> {
+    let (algorithm, hex) = digest
+        .split_once(':')
+        .with_context(|| format!("invalid digest '{}'", digest))?;
+    Ok(dir.join("blobs").join(algorithm).join(hex))
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    let json = read(path).with_context(|| format!("reading {}", path.display()))?;
+    serde_json::from_slice(&json).with_context(|| format!("parsing {}", path.display()))
+}
+
+fn digest(json: &Value, pointer: &str) -> Result<String> {
+    json.pointer(pointer)
+        .and_then(Value::as_str)
+        .map(<_>::to_string)
+        .ok_or_else(|| anyhow!("OCI image has no '{}'", pointer))
+}
+
+/// Layers of the image, checking that it is an s390x one
+fn layers(dir: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let index = read_json(&dir.join("index.json"))?;
+    let manifest = read_json(&blob(dir, &digest(&index, "/manifests/0/digest")?)?)?;
+    let config = read_json(&blob(dir, &digest(&manifest, "/config/digest")?)?)?;
+    match config.get("architecture").and_then(Value::as_str) {
+        Some("s390x") => (),
+        arch => bail!(
+            "OCI image is for {}, not s390x",
+            arch.unwrap_or("an unknown architecture")
+        ),
+    }
+    manifest
+        .get("layers")
+        .and_then(Value::as_array)
+        .context("OCI image has no layers")?
+        .iter()
+        .map(|layer| {
+            let media_type = layer
+                .get("mediaType")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            Ok((
+                blob(dir, &digest(layer, "/digest")?)?,
+                media_type.to_string(),
+            ))
+        })
+        .collect()
+}
+
+/// tar flag decompressing a layer of `media_type`
+fn decompress_flag(media_type: &str) -> &'static str {
+    if media_type.ends_with("+zstd") {
+        "--zstd"
+    } else if media_type.ends_with("+gzip") || media_type.ends_with(".gzip") {
+        "-z"
+    } else {
+        "--auto-compress"
+    }
+}
+
+/// Entry of a layer listing holding the live image with `suffix`
+fn find_entry<'a>(listing: &'a str, suffix: &str) -> Option<&'a str> {
+    listing
+        .lines()
+        .find(|entry| entry.ends_with(&format!("-{}", suffix)))
+}
+
+/// Pulls the image with skopeo and extracts its live images into the
+/// image directory, where they are then used as local live images.
+/// Images extracted before are reused.
+pub fn extract_oci(oci: &Oci) -> Result<()> {
+    let live = oci.live();
+    let targets = [
+        local_path(&live.kernel)?,
+        local_path(&live.initrd)?,
+        local_path(&live.rootfs)?,
+    ];
+    if targets.iter().all(|t| t.exists()) {
+        return Ok(());
+    }
+
+    let mut dir = targets[0].clone().into_os_string();
+    dir.push(".oci");
+    let dir = PathBuf::from(dir);
+    let transport = ["://", "oci:", "oci-archive:", "docker-archive:"]
+        .iter()
+        .any(|t| oci.image.contains(t));
+    let source = if transport {
+        oci.image.clone()
+    } else {
+        format!("docker://{}", oci.image)
+    };
+    info!("Pulling {}", source);
+    output(
+        Command::new("skopeo")
+            .args(["copy", "--override-arch", "s390x", "--override-os", "linux"])
+            .arg(&source)
+            .arg(format!("oci:{}:zvmhelper", dir.display())),
+    )?;
+
+    let result = (|| {
+        let layers = layers(&dir)?;
+        for (suffix, target) in LIVE_SUFFIXES.iter().zip(&targets) {
+            // the topmost layer wins
+            let found = layers.iter().rev().find_map(|(layer, media_type)| {
+                let listing = output(
+                    Command::new("tar")
+                        .arg(decompress_flag(media_type))
+                        .arg("-tf")
+                        .arg(layer),
+                )
+                .ok()?;
+                find_entry(&listing, suffix).map(|e| (layer, media_type, e.to_string()))
+            });
+            let (layer, media_type, entry) =
+                found.with_context(|| format!("OCI image {} has no *-{}", oci.image, suffix))?;
+            info!("Extracting {} to {}", entry, target.display());
+            let file =
+                File::create(target).with_context(|| format!("creating {}", target.display()))?;
+            let status = Command::new("tar")
+                .arg(decompress_flag(media_type))
+                .arg("-xOf")
+                .arg(layer)
+                .arg(&entry)
+                .stdout(Stdio::from(file))
+                .status()
+                .context("running tar")?;
+            if !status.success() {
+                let _ = std::fs::remove_file(target);
+                bail!("extracting {} failed with {}", entry, status);
+            }
+        }
+        Ok(())
+    })();
+    if let Err(e) = remove_dir_all(&dir) {
+        warn!("Removing {}: {}", dir.display(), e);
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn oci_layout() {
+        let dir = std::env::temp_dir().join(format!("zvmhelper-oci-{}", std::process::id()));
+        let blobs = dir.join("blobs/sha256");
+        std::fs::create_dir_all(&blobs).unwrap();
+        let write = |name: &str, json: Value| {
+            std::fs::write(blobs.join(name), json.to_string()).unwrap();
+        };
+        std::fs::write(
+            dir.join("index.json"),
+            r#"{"manifests": [{"digest": "sha256:m"}]}"#,
+        )
+        .unwrap();
+        write(
+            "m",
+            serde_json::json!({
+                "config": {"digest": "sha256:c"},
+                "layers": [{"digest": "sha256:l1", "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip"}],
+            }),
+        );
+        write("c", serde_json::json!({"architecture": "s390x"}));
+        let layers = layers(&dir).unwrap();
+        assert_eq!(layers[0].0, blobs.join("l1"));
+        assert_eq!(decompress_flag(&layers[0].1), "-z");
+
+        write("c", serde_json::json!({"architecture": "amd64"}));
+        let err = super::layers(&dir).unwrap_err();
+        assert_eq!(err.to_string(), "OCI image is for amd64, not s390x");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn layer_entries() {
+        let listing = "\
+usr/
+usr/share/coreos/fedora-coreos-39.20231101.3.0-live-kernel-s390x
+usr/share/coreos/fedora-coreos-39.20231101.3.0-live-initramfs.s390x.img
+";
+        assert_eq!(
+            find_entry(listing, "live-kernel-s390x"),
+            Some("usr/share/coreos/fedora-coreos-39.20231101.3.0-live-kernel-s390x")
+        );
+        assert_eq!(find_entry(listing, "live-rootfs.s390x.img"), None);
+    }
+}
@@ -0,0 +1,189 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{stdout, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+const WIDTH: usize = 30;
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+const REDRAW: Duration = Duration::from_millis(200);
+/// Interval of the progress lines of parallel transfers
+const LINES: Duration = Duration::from_secs(5);
+
+/// Writer drawing a progress bar of the bytes going through it on the
+/// terminal, a spinner when the total is unknown. Labelled ones print a
+/// line every few seconds instead, so parallel transfers don't overwrite
+/// each other's bar.
+pub struct Progress<W> {
+    inner: W,
+    total: Option<u64>,
+    done: u64,
+    /// Bytes done before, not counted in the throughput
+    base: u64,
+    started: Instant,
+    drawn: Option<Instant>,
+    ticks: usize,
+    label: Option<String>,
+}
+
+/// `1.5 MiB` form of `bytes`
+pub fn human(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Progress line of `done` bytes out of `total` at `rate` bytes/s, `tick`
+/// selects the spinner frame
+fn line(done: u64, total: Option<u64>, rate: f64, tick: usize) -> String {
+    let rate = human(rate as u64);
+    match total {
+        Some(total) if total > 0 => {
+            let ratio = (done as f64 / total as f64).min(1.0);
+            let filled = (ratio * WIDTH as f64) as usize;
+            format!(
+                "[{}{}] {:>3}% {}/{} {}/s",
+                "#".repeat(filled),
+                " ".repeat(WIDTH - filled),
+                (ratio * 100.0) as u64,
+                human(done),
+                human(total),
+                rate
+            )
+        }
+        _ => format!(
+            "{} {} {}/s",
+            SPINNER[tick % SPINNER.len()],
+            human(done),
+            rate
+        ),
+    }
+}
+
+impl<W: Write> Progress<W> {
+    /// Wraps `inner`, drawing only when `enabled` and stdout is a terminal
+    pub fn new(inner: W, total: Option<u64>, enabled: bool) -> Self {
+        Self {
+            inner,
+            total,
+            done: 0,
+            base: 0,
+            started: Instant::now(),
+            drawn: if enabled && stdout().is_terminal() {
+                Some(Instant::now() - REDRAW)
+            } else {
+                None
+            },
+            ticks: 0,
+            label: None,
+        }
+    }
+
+    /// Prints progress lines prefixed with `label` instead of a bar
+    pub fn labelled(mut self, label: &str) -> Self {
+        self.label = Some(label.to_string());
+        // the first line once the interval passed, none for small files
+        self.drawn = self.drawn.map(|_| Instant::now());
+        self
+    }
+
+    fn interval(&self) -> Duration {
+        match self.label {
+            Some(_) => LINES,
+            None => REDRAW,
+        }
+    }
+
+    /// Starts at `done` bytes, of a resumed transfer
+    pub fn resumed(mut self, done: u64) -> Self {
+        self.done = done;
+        self.base = done;
+        self
+    }
+
+    fn draw(&mut self) {
+        let rate = (self.done - self.base) as f64 / self.started.elapsed().as_secs_f64().max(0.001);
+        let line = line(self.done, self.total, rate, self.ticks);
+        let mut out = stdout();
+        let _ = match &self.label {
+            Some(label) => writeln!(out, "{}: {}", label, line),
+            None => write!(out, "\r{}\x1b[K", line),
+        };
+        let _ = out.flush();
+        self.ticks += 1;
+    }
+
+    /// Draws the final state and ends the line
+    pub fn finish(&mut self) {
+        if self.drawn.is_some() {
+            self.draw();
+            if self.label.is_none() {
+                println!();
+            }
+            self.drawn = None;
+        }
+    }
+}
+
+impl<W: Write> Write for Progress<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.done += n as u64;
+        if let Some(drawn) = self.drawn {
+            if drawn.elapsed() >= self.interval() {
+                self.draw();
+                self.drawn = Some(Instant::now());
+            }
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn progress_lines() {
+        assert_eq!(human(512), "512 B");
+        assert_eq!(human(1536), "1.5 KiB");
+        assert_eq!(human(1024 * 1024 * 1024), "1.0 GiB");
+        assert_eq!(
+            line(512 * 1024, Some(1024 * 1024), 2048.0, 0),
+            "[###############               ]  50% 512.0 KiB/1.0 MiB 2.0 KiB/s"
+        );
+        assert_eq!(line(100, None, 100.0, 1), "/ 100 B 100 B/s");
+
+        let labelled = Progress::new(Vec::new(), None, false).labelled("kernel");
+        assert_eq!(labelled.interval(), LINES);
+        assert_eq!(labelled.drawn, None);
+
+        let mut progress = Progress::new(Vec::new(), None, false);
+        progress.write_all(b"kernel").unwrap();
+        progress.finish();
+        assert_eq!(progress.done, 6);
+        assert_eq!(progress.inner, b"kernel");
+    }
+}
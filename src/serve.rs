@@ -0,0 +1,239 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::cmdline::InstallConfig;
+use crate::config::ServeAddr;
+use crate::ignition::merged_path;
+use anyhow::{bail, Context, Result};
+use log::warn;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{sleep, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Embedded HTTP server handing the Ignition config to the guest, it
+/// stops when dropped
+pub struct Server {
+    pub url: String,
+    fetched: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// Local address the guest reaches us through, the source address of
+/// the route to its static IP
+fn route_to(cfg: &InstallConfig) -> Result<IpAddr> {
+    let Some(guest) = cfg.static_ip() else {
+        bail!(
+            "the serving address can't be derived without a static --ip, use --ignition-serve <addr>:<port>"
+        );
+    };
+    let any = if guest.ip.is_ipv6() {
+        "[::]:0"
+    } else {
+        "0.0.0.0:0"
+    };
+    let socket = UdpSocket::bind(any).context("finding the route to the guest")?;
+    socket
+        .connect((guest.ip, 9))
+        .and_then(|_| socket.local_addr())
+        .map(|addr| addr.ip())
+        .with_context(|| format!("finding the route to {}", guest.ip))
+}
+
+/// Answers a single request, returns whether the config was served
+fn respond(stream: &mut TcpStream, name: &str, file: &PathBuf) -> Result<bool> {
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 8192 {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut line = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    let (status, body) = match (line.next(), line.next()) {
+        (Some("GET"), Some(path)) if path.trim_start_matches('/') == name => (
+            "200 OK",
+            std::fs::read(file).with_context(|| format!("reading '{}'", file.display()))?,
+        ),
+        (Some("GET"), _) => ("404 Not Found", Vec::new()),
+        _ => ("405 Method Not Allowed", Vec::new()),
+    };
+    write!(
+        stream,
+        "HTTP/1.0 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    Ok(status == "200 OK")
+}
+
+/// URL a [`Server`] for `addr` serves the config of `cfg` at, without
+/// binding it. The port of an ephemeral one is only known once bound,
+/// `<port>` stands for it.
+pub fn served_url(cfg: &InstallConfig, addr: &ServeAddr) -> Result<String> {
+    let ip = match addr.ip {
+        Some(ip) => ip,
+        None => route_to(cfg)?,
+    };
+    let host = match ip {
+        IpAddr::V6(ip) => format!("[{}]", ip),
+        IpAddr::V4(ip) => ip.to_string(),
+    };
+    let port = match addr.port {
+        0 => "<port>".to_string(),
+        port => port.to_string(),
+    };
+    let file = merged_path(&cfg.zvm)?;
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    Ok(format!("http://{}:{}/{}", host, port, name))
+}
+
+impl Server {
+    /// Binds `--ignition-serve` and serves the merged Ignition config of
+    /// `cfg` in the background
+    pub fn start(cfg: &InstallConfig, addr: &ServeAddr) -> Result<Self> {
+        let ip = match addr.ip {
+            Some(ip) => ip,
+            None => route_to(cfg)?,
+        };
+        let listener = TcpListener::bind(SocketAddr::new(ip, addr.port)).map_err(|e| {
+            if e.kind() == ErrorKind::AddrInUse {
+                anyhow::anyhow!(
+                    "{}:{} is already in use, pick another port with --ignition-serve",
+                    ip,
+                    addr.port
+                )
+            } else {
+                anyhow::Error::new(e).context(format!("binding {}:{}", ip, addr.port))
+            }
+        })?;
+        listener.set_nonblocking(true)?;
+        let local = listener.local_addr()?;
+        let file = merged_path(&cfg.zvm)?;
+        let name = file
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let url = format!("http://{}/{}", local, name);
+
+        let fetched = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let (fetched, stop) = (fetched.clone(), stop.clone());
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        Ok((mut stream, peer)) => {
+                            let _ = stream.set_nonblocking(false);
+                            match respond(&mut stream, &name, &file) {
+                                Ok(true) => fetched.store(true, Ordering::Relaxed),
+                                Ok(false) => (),
+                                Err(e) => warn!("Serving {}: {:#}", peer, e),
+                            }
+                        }
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                            sleep(Duration::from_millis(100))
+                        }
+                        Err(e) => warn!("Serving the Ignition config: {}", e),
+                    }
+                }
+            })
+        };
+        Ok(Self {
+            url,
+            fetched,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    /// Waits up to `timeout` for the guest to fetch its config
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while !self.fetched.load(Ordering::Relaxed) && Instant::now() < deadline {
+            sleep(Duration::from_millis(500));
+        }
+        self.fetched.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cmdline::Cmd;
+    use crate::images::TestImageDir;
+    use clap::Parser;
+
+    #[test]
+    fn serves_merged_config() {
+        let _dir = TestImageDir::new("serve");
+        let zvm = format!("serve{}", std::process::id());
+        let args = [
+            "zvmhelper",
+            "install",
+            "-z",
+            &zvm,
+            "-i",
+            "http://ign",
+            "-q",
+            "artifacts",
+        ];
+        let cfg = Cmd::try_parse_from(args).unwrap().config();
+        let file = merged_path(&zvm).unwrap();
+        std::fs::write(&file, r#"{"ignition": {"version": "3.4.0"}}"#).unwrap();
+
+        let addr = "127.0.0.1:0".parse().unwrap();
+        let server = Server::start(&cfg, &addr).unwrap();
+        assert!(!server.wait(Duration::ZERO));
+        let missing = reqwest::blocking::get(format!("{}.missing", server.url)).unwrap();
+        assert_eq!(missing.status(), 404);
+        let body = reqwest::blocking::get(&server.url).unwrap().text().unwrap();
+        assert_eq!(body, r#"{"ignition": {"version": "3.4.0"}}"#);
+        assert!(server.wait(Duration::from_secs(5)));
+
+        let port = server
+            .url
+            .split(':')
+            .nth(2)
+            .unwrap()
+            .split('/')
+            .next()
+            .unwrap();
+        let taken = format!("127.0.0.1:{}", port).parse().unwrap();
+        let err = Server::start(&cfg, &taken).err().unwrap();
+        assert!(err.to_string().contains("already in use"), "{}", err);
+        drop(server);
+        assert!(file.exists());
+    }
+}
@@ -0,0 +1,206 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::cmdline::{Build, CoreOS, Images, InstallConfig, Live};
+use crate::images::fetch_public;
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use reqwest::Url;
+use serde_json::Value;
+
+/// Where the FCOS stream metadata is published, as `<stream>.json`
+const STREAMS: &str = "https://builds.coreos.fedoraproject.org/streams";
+/// OpenShift installer sources, with the RHCOS stream metadata of each
+/// `release-<version>` branch
+const RHCOS: &str = "https://raw.githubusercontent.com/openshift/installer";
+
+/// Release and live images of the s390x PXE artifacts in stream metadata
+fn stream_live(metadata: &Value) -> Result<(String, Live)> {
+    let metal = metadata
+        .pointer("/architectures/s390x/artifacts/metal")
+        .context("missing architectures.s390x.artifacts.metal")?;
+    let release = metal["release"]
+        .as_str()
+        .context("no release of the s390x metal artifacts")?;
+    let artifact = |name: &str| -> Result<(Url, Option<String>)> {
+        let artifact = &metal["formats"]["pxe"][name];
+        let location = artifact["location"]
+            .as_str()
+            .with_context(|| format!("no location of the PXE {}", name))?;
+        let url = Url::parse(location)
+            .with_context(|| format!("invalid location of the PXE {}", name))?;
+        Ok((url, artifact["sha256"].as_str().map(str::to_string)))
+    };
+    let (kernel, kernel_sha256) = artifact("kernel")?;
+    let (initrd, initrd_sha256) = artifact("initramfs")?;
+    let (rootfs, _) = artifact("rootfs")?;
+    Ok((
+        release.to_string(),
+        Live {
+            kernel,
+            initrd,
+            rootfs,
+            kernel_sha256,
+            initrd_sha256,
+            mirrors: Vec::new(),
+        },
+    ))
+}
+
+/// Where the metadata of the latest release is published: the FCOS
+/// --stream, or the RHCOS boot images pinned by the OpenShift installer
+/// of the --ocp-version
+fn metadata_url(build: &Build) -> Result<Option<(String, Url)>> {
+    let (what, url) = match (&build.variant, &build.stream, &build.ocp_version) {
+        (CoreOS::FCOS, Some(stream), None) => (
+            format!("stream '{}'", stream),
+            format!("{}/{}.json", STREAMS, stream),
+        ),
+        (CoreOS::RHCOS, None, Some(version)) => (
+            format!("OCP {}", version),
+            format!("{}/release-{}/data/data/coreos/rhcos.json", RHCOS, version),
+        ),
+        (CoreOS::RHCOS, Some(_), _) => bail!("--stream is only available for FCOS"),
+        (CoreOS::FCOS, _, Some(_)) => bail!("--ocp-version is only available for RHCOS"),
+        (CoreOS::RHCOS, None, None) if build.time.is_none() => {
+            bail!("RHCOS artifacts require --time or --ocp-version")
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some((what, Url::parse(&url)?)))
+}
+
+/// Resolves `artifacts --stream` or `--ocp-version` to the images of the
+/// latest release, the build options stay in use when the metadata is
+/// unreachable
+pub fn resolve(config: &mut InstallConfig) -> Result<()> {
+    let Images::Artifacts(build) = &config.images else {
+        return Ok(());
+    };
+    let Some((what, url)) = metadata_url(build)? else {
+        return Ok(());
+    };
+    // RHCOS file names can't be built without the time
+    let manual = matches!(build.variant, CoreOS::FCOS) || build.time.is_some();
+    // public metadata, the credentials are for the builder
+    let fetched = fetch_public(config, &url)
+        .and_then(|json| serde_json::from_slice::<Value>(&json).context("metadata is not JSON"));
+    let metadata = match fetched {
+        Ok(metadata) => metadata,
+        Err(e) if manual => {
+            warn!(
+                "Metadata of {} unreachable at {}, using the build options: {:#}",
+                what, url, e
+            );
+            return Ok(());
+        }
+        Err(e) => {
+            return Err(e.context(format!("metadata of {} unreachable at {}", what, url)));
+        }
+    };
+    let (release, live) =
+        stream_live(&metadata).with_context(|| format!("no s390x artifacts in {}", what))?;
+    info!("Using release {} of {}", release, what);
+    if let Images::Artifacts(build) = &mut config.images {
+        // 40.20240416.3.1 is a Fedora 40 release, 413.92.202303141019-0
+        // an OCP 4.13 one
+        if let Some(major) = release.split('.').next() {
+            build.version = major.to_string();
+        }
+        build.stream_images = Some(Box::new(live));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cmdline::test::install_with;
+
+    #[test]
+    fn stream_metadata() {
+        let metadata = serde_json::json!({
+            "stream": "stable",
+            "architectures": {
+                "s390x": {
+                    "artifacts": {
+                        "metal": {
+                            "release": "40.20240416.3.1",
+                            "formats": {
+                                "pxe": {
+                                    "kernel": {
+                                        "location": "https://builds/fcos-40-live-kernel-s390x",
+                                        "sha256": "aa",
+                                    },
+                                    "initramfs": {
+                                        "location": "https://builds/fcos-40-live-initramfs.s390x.img",
+                                        "sha256": "bb",
+                                    },
+                                    "rootfs": {
+                                        "location": "https://builds/fcos-40-live-rootfs.s390x.img",
+                                    },
+                                },
+                            },
+                        },
+                    },
+                },
+            },
+        });
+        let (release, live) = stream_live(&metadata).unwrap();
+        assert_eq!(release, "40.20240416.3.1");
+        assert_eq!(
+            live.kernel.as_str(),
+            "https://builds/fcos-40-live-kernel-s390x"
+        );
+        assert_eq!(live.kernel_sha256.as_deref(), Some("aa"));
+        assert_eq!(live.initrd_sha256.as_deref(), Some("bb"));
+        assert_eq!(
+            live.rootfs.as_str(),
+            "https://builds/fcos-40-live-rootfs.s390x.img"
+        );
+
+        let err = stream_live(&serde_json::json!({"architectures": {"x86_64": {}}})).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "missing architectures.s390x.artifacts.metal"
+        );
+    }
+
+    #[test]
+    fn metadata_urls() {
+        let build = |extra: &[&str]| {
+            let cfg = install_with("http://ign", &[], extra).unwrap();
+            match cfg.images {
+                Images::Artifacts(build) => build,
+                _ => unreachable!(),
+            }
+        };
+        let (what, url) = metadata_url(&build(&["--stream", "next"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(what, "stream 'next'");
+        assert_eq!(
+            url.as_str(),
+            "https://builds.coreos.fedoraproject.org/streams/next.json"
+        );
+        let rhcos = build(&["--variant", "rhcos", "--ocp-version", "4.13"]);
+        let (what, url) = metadata_url(&rhcos).unwrap().unwrap();
+        assert_eq!(what, "OCP 4.13");
+        assert_eq!(
+            url.as_str(),
+            "https://raw.githubusercontent.com/openshift/installer/release-4.13/data/data/coreos/rhcos.json"
+        );
+        assert!(metadata_url(&build(&[])).unwrap().is_none());
+        let err = metadata_url(&build(&["--variant", "rhcos", "--stream", "next"])).unwrap_err();
+        assert_eq!(err.to_string(), "--stream is only available for FCOS");
+    }
+}
@@ -0,0 +1,165 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::cmdline::{InstallConfig, OutputFormat};
+use crate::images::{exists, local_path, redacted, sha256_file};
+use crate::ipl::{generate_parm, parm_file};
+use crate::{ignition, network};
+use anyhow::{bail, Result};
+use reqwest::Url;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+/// Checks run by `zvmhelper verify`
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub passed: bool,
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    fn check(&mut self, name: &str, result: Result<String>) {
+        let (passed, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(e) => (false, format!("{:#}", e)),
+        };
+        self.checks.push(Check {
+            name: name.to_string(),
+            passed,
+            detail,
+        });
+    }
+
+    fn print(&self, cfg: &InstallConfig) {
+        if cfg.output == OutputFormat::Json {
+            match serde_json::to_string_pretty(self) {
+                Ok(json) => println!("{}", json),
+                Err(e) => eprintln!("Serializing verify report: {}", e),
+            }
+            return;
+        }
+        for check in &self.checks {
+            let status = if check.passed { "PASS" } else { "FAIL" };
+            println!("{} {}: {}", status, check.name, check.detail);
+        }
+        println!("Verify {}", if self.passed { "passed" } else { "failed" });
+    }
+}
+
+/// A staged image is hashed, a missing one must be reachable
+fn image(cfg: &InstallConfig, url: &Url) -> Result<String> {
+    let path = local_path(url)?;
+    if path.exists() {
+        return Ok(format!(
+            "{} staged, sha256 {}",
+            path.display(),
+            sha256_file(&path)?
+        ));
+    }
+    exists(cfg, url)?;
+    Ok(format!("{} reachable, not staged", redacted(url)))
+}
+
+/// Runs the install checks that have no side effects: nothing is
+/// downloaded or punched and no device is brought online
+pub fn verify(cfg: &InstallConfig) -> Result<()> {
+    let mut report = Report::default();
+    report.check(
+        "disk",
+        cfg.disk().map(|disk| match disk {
+            Some(disk) => disk.to_string(),
+            None => "no install disk".to_string(),
+        }),
+    );
+    report.check("ip", cfg.ip_kargs().map(|ips| ips.join(" ")));
+    report.check(
+        "parm",
+        generate_parm(cfg)
+            .and_then(|parm| parm_file(cfg, &parm))
+            .map(|file| format!("{} bytes", file.len())),
+    );
+    let live = cfg.images.live()?;
+    report.check("kernel", image(cfg, &live.kernel));
+    report.check("initrd", image(cfg, &live.initrd));
+    let rootfs = cfg.rootfs_url.clone().unwrap_or(live.rootfs);
+    report.check(
+        "rootfs",
+        exists(cfg, &rootfs).map(|_| format!("{} reachable", redacted(&rootfs))),
+    );
+    report.check(
+        "ignition",
+        ignition::check(cfg).map(|_| format!("{} is a supported config", cfg.ignition)),
+    );
+    if cfg.check_network {
+        report.check("network", network::check(cfg).map(str::to_string));
+    }
+    report.passed = report.checks.iter().all(|c| c.passed);
+    report.print(cfg);
+    if !report.passed {
+        let failed = report.checks.iter().filter(|c| !c.passed).count();
+        bail!("{} of {} checks failed", failed, report.checks.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cmdline::Cmd;
+    use clap::Parser;
+
+    #[test]
+    fn local_install() {
+        let dir = std::env::temp_dir().join(format!("zvmhelper-verify-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["kernel", "initrd", "rootfs"] {
+            std::fs::write(dir.join(name), name).unwrap();
+        }
+        std::fs::write(
+            dir.join("ign.json"),
+            r#"{"ignition": {"version": "3.4.0"}}"#,
+        )
+        .unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let ignition = Url::from_file_path(dir.join("ign.json")).unwrap();
+        let args = [
+            "zvmhelper",
+            "verify",
+            "-i",
+            ignition.as_str(),
+            "--dasd",
+            "0.0.5c6e",
+            "--output",
+            "json",
+            "live-images",
+            "--kernel",
+            &path("kernel"),
+            "--initrd",
+            &path("initrd"),
+            "--rootfs",
+            &path("rootfs"),
+        ];
+        let cfg = Cmd::try_parse_from(args).unwrap().config();
+        verify(&cfg).unwrap();
+
+        std::fs::remove_file(dir.join("initrd")).unwrap();
+        let err = verify(&cfg).unwrap_err();
+        assert_eq!(err.to_string(), "1 of 7 checks failed");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
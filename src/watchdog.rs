@@ -0,0 +1,68 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::cmdline::InstallConfig;
+use crate::error::Error;
+use crate::images::remove_partial_downloads;
+use crate::ipl::{kill_running, restore_devices};
+use anyhow::Result;
+use log::error;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::sync::Mutex;
+
+/// Stage of the install being run, reported when the deadline hits
+static STAGE: Mutex<&str> = Mutex::new("start");
+
+pub fn set_stage(stage: &'static str) {
+    *STAGE.lock().unwrap() = stage;
+}
+
+/// Runs `install` on its own thread and waits for it up to `--deadline`.
+/// Once exceeded, running commands are killed, the vmur devices restored
+/// and partial downloads removed, an [`Error::DeadlineExceeded`] is
+/// returned while the abandoned thread ends with the process.
+pub fn run<F>(config: &InstallConfig, install: F) -> Result<()>
+where
+    F: FnOnce() -> Result<()> + Send + 'static,
+{
+    let Some(deadline) = config.deadline else {
+        return install();
+    };
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(install());
+    });
+    match rx.recv_timeout(deadline) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Disconnected) => Err(anyhow::anyhow!("the install thread panicked")),
+        Err(RecvTimeoutError::Timeout) => {
+            let stage = *STAGE.lock().unwrap();
+            error!(
+                "Deadline of {}s exceeded during the '{}' stage, aborting",
+                deadline.as_secs(),
+                stage
+            );
+            kill_running();
+            if let Err(e) = restore_devices(config) {
+                error!("Restoring devices: {:#}", e);
+            }
+            if let Err(e) = remove_partial_downloads(config) {
+                error!("Removing partial downloads: {:#}", e);
+            }
+            Err(Error::DeadlineExceeded {
+                deadline,
+                stage: stage.to_string(),
+            }
+            .into())
+        }
+    }
+}